@@ -0,0 +1,237 @@
+use crate::log_entry::LogHeader;
+use std::time::Duration;
+
+/// 批次中的一个操作
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+/// 原子写入批次
+///
+/// 通过 `KVStore::write_batch` 提交，批次中的所有操作要么全部生效，要么全部不生效。
+///
+/// 日志布局：批次内每个条目都带有 BATCH 标志，最后追加一个提交标记 (记录条目数)。
+/// 恢复时只有读到完整且校验通过的提交标记，批次中的条目才会进入索引。
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: Vec<u8>) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            key: key.into(),
+            value,
+            ttl: None,
+        });
+        self
+    }
+
+    /// 带 TTL 的写入，过期时间在提交时计算
+    pub fn put_with_ttl(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            key: key.into(),
+            value,
+            ttl: Some(ttl),
+        });
+        self
+    }
+
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Delete { key: key.into() });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    pub(crate) fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
+
+/// 按 WriteBatch 语义回放日志条目 (恢复索引、生成 Hint 文件时使用)
+///
+/// 批次内的条目先缓存起来，读到提交标记时整体输出；普通条目直接输出。
+/// 同一个批次的条目在文件中总是连续的，并且紧跟着提交标记，因此：
+/// - 提交标记只认领它之前最近的 `count` 个批次条目，且序列号必须连续并以标记的序列号结尾；
+/// - 更早的缓存条目、被普通条目打断的批次、文件结尾处没有提交标记的批次都属于未完成的批次，直接丢弃。
+pub struct BatchReplay<T> {
+    pending: Vec<(u64, T)>,
+    dropped: usize,
+}
+
+impl<T> BatchReplay<T> {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    /// 输入一个解码得到的条目；`item` 将数据条目转换为待输出的记录，`apply` 输出生效的记录
+    pub fn feed<E>(
+        &mut self,
+        header: LogHeader,
+        item: impl FnOnce(LogHeader) -> T,
+        mut apply: impl FnMut(T) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if header.is_batch_commit() {
+            let count = header.batch_count().map_or(usize::MAX, |c| c as usize);
+            let start = match self.pending.len().checked_sub(count) {
+                Some(start) if self.is_complete(start, header.sequence_number) => start,
+                _ => self.pending.len(),
+            };
+            let committed: Vec<(u64, T)> = self.pending.drain(start..).collect();
+            self.abort();
+            for (_, t) in committed {
+                apply(t)?;
+            }
+        } else if header.is_batch() {
+            let seq = header.sequence_number;
+            self.pending.push((seq, item(header)));
+        } else {
+            self.abort();
+            apply(item(header))?;
+        }
+        Ok(())
+    }
+
+    /// 文件读取结束：丢弃没有提交的批次条目，返回累计丢弃的条目数
+    pub fn finish(&mut self) -> usize {
+        self.abort();
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// pending[start..] 的序列号是否连续且以 last_seq 结尾
+    fn is_complete(&self, start: usize, last_seq: u64) -> bool {
+        let batch = &self.pending[start..];
+        batch.last().is_none_or(|(seq, _)| *seq == last_seq)
+            && batch.windows(2).all(|w| w[0].0 + 1 == w[1].0)
+    }
+
+    fn abort(&mut self) {
+        self.dropped += self.pending.len();
+        self.pending.clear();
+    }
+}
+
+impl<T> Default for BatchReplay<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_entry::{Decoder, LogEntry};
+    use std::convert::Infallible;
+    use std::io::Cursor;
+
+    fn batch_entry(key: &str, seq: u64) -> LogEntry {
+        let mut entry = LogEntry::new(key, b"v".to_vec(), seq).build();
+        entry.mark_batch();
+        entry
+    }
+
+    /// 编码一串条目后再回放，返回生效的 Key 和丢弃数
+    fn replay(entries: &[LogEntry]) -> (Vec<String>, usize) {
+        let mut buf = Vec::new();
+        for entry in entries {
+            entry.encode_to(&mut buf).unwrap();
+        }
+        let mut cursor = Cursor::new(buf);
+        let mut decoder = Decoder::new(1024, 1024);
+        let mut replay = BatchReplay::new();
+        let mut applied = Vec::new();
+        while let Some(header) = decoder.decode_header_and_key(&mut cursor).unwrap() {
+            cursor.set_position(cursor.position() + 4 + header.val_len as u64);
+            replay
+                .feed(
+                    header,
+                    |h| String::from_utf8(h.key).unwrap(),
+                    |k| {
+                        applied.push(k);
+                        Ok::<_, Infallible>(())
+                    },
+                )
+                .unwrap();
+        }
+        (applied, replay.finish())
+    }
+
+    #[test]
+    fn test_write_batch_builder() {
+        let mut batch = WriteBatch::new();
+        batch.put("a", b"1".to_vec()).delete("b");
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.ops()[1], BatchOp::Delete { key: b"b".to_vec() });
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_replay_committed_batch() {
+        let (applied, dropped) = replay(&[
+            LogEntry::new("a", b"v".to_vec(), 1).build(),
+            batch_entry("b", 2),
+            batch_entry("c", 3),
+            LogEntry::new_batch_commit(2, 3),
+            LogEntry::new("d", b"v".to_vec(), 4).build(),
+        ]);
+        assert_eq!(applied, vec!["a", "b", "c", "d"]);
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn test_replay_drops_incomplete_batch() {
+        let (applied, dropped) = replay(&[
+            // 被普通条目打断的批次
+            batch_entry("x", 1),
+            LogEntry::new("a", b"v".to_vec(), 2).build(),
+            // 未提交的批次后紧跟另一个已提交的批次
+            batch_entry("y", 3),
+            batch_entry("b", 4),
+            batch_entry("c", 5),
+            LogEntry::new_batch_commit(2, 5),
+            // 序列号不匹配的提交标记
+            batch_entry("z", 6),
+            LogEntry::new_batch_commit(1, 7),
+            // 文件结尾处没有提交标记
+            batch_entry("w", 8),
+        ]);
+        assert_eq!(applied, vec!["a", "b", "c"]);
+        assert_eq!(dropped, 4);
+    }
+}
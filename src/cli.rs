@@ -0,0 +1,430 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use titanium_engine::config::{ConfigWatcher, DEFAULT_CONFIG_FILE};
+use titanium_engine::error::TitaniumError;
+use titanium_engine::kv::{KVStore, MAX_TTL};
+use titanium_engine::log_entry::LogEntry;
+use titanium_engine::storage::OsFileSystem;
+use titanium_engine::utils::{base64_encode, json_string};
+
+pub const USAGE: &str = "\
+Usage: titanium_engine [OPTIONS] [COMMAND]
+
+Without a command, starts the interactive shell.
+
+Commands:
+  get <key> [--raw]                 Print an entry as JSON (--raw: value bytes only)
+  set <key> <value|-> [--ttl SECS]  Write a value (\"-\" reads it from stdin)
+  rm <key>                          Remove a key
+  scan [--prefix P] [--limit N]     Print matching entries as JSON lines
+  stats                             Print store statistics as JSON
+  compact                           Merge data files, print stats before and after
+  serve [addr]                      Serve the Redis protocol (RESP2)
+  serve-http [addr]                 Serve the HTTP/JSON API
+  upgrade [data_dir]                Upgrade old-format data files in place
+
+Options:
+  --config <file>     Config file (default: titanium.conf)
+  --data-dir <dir>    Override data_dir from the config file
+  -h, --help          Show this help
+
+Exit codes: 0 success, 1 key not found, 2 usage error, 3 store error";
+
+pub const EXIT_NOT_FOUND: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_ERROR: u8 = 3;
+
+/// 命令行参数：全局选项可以出现在子命令前后，`--` 之后的参数都视为位置参数
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub command: Option<String>,
+    pub positional: Vec<String>,
+    pub config: Option<String>,
+    pub data_dir: Option<String>,
+    pub ttl: Option<u64>,
+    pub prefix: Option<String>,
+    pub limit: Option<usize>,
+    pub raw: bool,
+    pub help: bool,
+    /// 出现过的子命令选项名，用于拒绝子命令不支持的选项
+    options: Vec<&'static str>,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            if options_done {
+                parsed.push_positional(arg);
+                continue;
+            }
+            if arg == "--" {
+                options_done = true;
+                continue;
+            }
+            if arg == "-h" || arg == "--help" {
+                parsed.help = true;
+                continue;
+            }
+            let Some(option) = arg.strip_prefix("--") else {
+                parsed.push_positional(arg);
+                continue;
+            };
+            let (name, inline) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            if name == "raw" {
+                parsed.raw = true;
+                parsed.options.push("raw");
+                continue;
+            }
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("Missing value for --{}", name)),
+            };
+            match name {
+                "config" => parsed.config = Some(value),
+                "data-dir" => parsed.data_dir = Some(value),
+                "ttl" => {
+                    parsed.ttl = match value.parse() {
+                        Ok(secs) if secs > 0 && secs <= MAX_TTL.as_secs() => Some(secs),
+                        _ => {
+                            return Err(format!(
+                                "Invalid --ttl '{}': expected seconds between 1 and {}",
+                                value,
+                                MAX_TTL.as_secs()
+                            ));
+                        }
+                    };
+                    parsed.options.push("ttl");
+                }
+                "prefix" => {
+                    parsed.prefix = Some(value);
+                    parsed.options.push("prefix");
+                }
+                "limit" => {
+                    parsed.limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid --limit '{}'", value))?,
+                    );
+                    parsed.options.push("limit");
+                }
+                _ => return Err(format!("Unknown option: --{}", name)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn push_positional(&mut self, arg: String) {
+        if self.command.is_none() {
+            self.command = Some(arg);
+        } else {
+            self.positional.push(arg);
+        }
+    }
+
+    pub fn config_file(&self) -> &str {
+        self.config.as_deref().unwrap_or(DEFAULT_CONFIG_FILE)
+    }
+
+    /// 检查子命令的位置参数个数和选项
+    fn expect(&self, min: usize, max: usize, allowed: &[&str]) -> Result<(), Failure> {
+        let command = self.command.as_deref().unwrap_or_default();
+        if self.positional.len() < min || self.positional.len() > max {
+            return Err(Failure::Usage(format!(
+                "Wrong number of arguments for '{}'",
+                command
+            )));
+        }
+        match self.options.iter().find(|o| !allowed.contains(o)) {
+            Some(option) => Err(Failure::Usage(format!(
+                "Option --{} is not supported by '{}'",
+                option, command
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 初始化全局配置并应用 `--data-dir`；显式指定的配置文件必须存在
+pub fn init_config(args: &Args) -> Result<(), TitaniumError> {
+    if let Some(path) = &args.config
+        && !Path::new(path).exists()
+    {
+        return Err(TitaniumError::ConfigError(format!(
+            "Config file '{}' not found",
+            path
+        )));
+    }
+    ConfigWatcher::init(args.config_file())?;
+    if let Some(data_dir) = &args.data_dir {
+        // data_dir 只在打开时读取，配置热加载覆盖回去也不影响已打开的 KVStore
+        let watcher = ConfigWatcher::global();
+        let mut config = watcher.get();
+        config.data_dir = data_dir.clone();
+        watcher.override_config(config);
+    }
+    Ok(())
+}
+
+enum Failure {
+    Usage(String),
+    NotFound,
+    Store(TitaniumError),
+}
+
+impl From<TitaniumError> for Failure {
+    fn from(e: TitaniumError) -> Self {
+        Failure::Store(e)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Store(e.into())
+    }
+}
+
+/// 执行一次性子命令，结果以 JSON 输出到 stdout，错误以 JSON 输出到 stderr
+pub fn run(args: &Args) -> ExitCode {
+    let result = match args.command.as_deref() {
+        Some("get") => get(args),
+        Some("set") => set(args),
+        Some("rm") => rm(args),
+        Some("scan") => scan(args),
+        Some("stats") => stats(args),
+        Some("compact") => compact(args),
+        Some(other) => Err(Failure::Usage(format!("Unknown command: {}", other))),
+        None => Err(Failure::Usage("Missing command".to_string())),
+    };
+    result.map_or_else(report, |()| ExitCode::SUCCESS)
+}
+
+/// 检查由 main 直接执行的子命令 (serve、upgrade 等) 的参数，用法错误时打印提示并返回退出码
+pub fn expect_args(args: &Args, min: usize, max: usize) -> Result<(), ExitCode> {
+    args.expect(min, max, &[]).map_err(report)
+}
+
+/// 输出错误并返回对应的退出码
+fn report(failure: Failure) -> ExitCode {
+    match failure {
+        Failure::Usage(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Failure::NotFound => {
+            eprintln!("{{\"error\":\"key not found\"}}");
+            ExitCode::from(EXIT_NOT_FOUND)
+        }
+        Failure::Store(e) => {
+            eprintln!("{{\"error\":{}}}", json_string(&e.to_string()));
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// 读命令以只读模式打开，不会创建目录或写入任何文件
+fn open(read_only: bool) -> Result<KVStore, TitaniumError> {
+    let watcher = ConfigWatcher::global().clone();
+    let data_dir = watcher.get().data_dir;
+    let mut builder = KVStore::builder(watcher, Arc::new(OsFileSystem));
+    if read_only {
+        if !Path::new(&data_dir).is_dir() {
+            return Err(TitaniumError::ConfigError(format!(
+                "Data directory '{}' does not exist",
+                data_dir
+            )));
+        }
+        builder = builder.read_only();
+    }
+    let mut kv = builder.open()?;
+    kv.restore()?;
+    Ok(kv)
+}
+
+fn get(args: &Args) -> Result<(), Failure> {
+    args.expect(1, 1, &["raw"])?;
+    let kv = open(true)?;
+    let entry = kv.get(&args.positional[0])?.ok_or(Failure::NotFound)?;
+    if args.raw {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&entry.value)?;
+        stdout.flush()?;
+    } else {
+        println!("{}", entry_json(&entry));
+    }
+    Ok(())
+}
+
+fn set(args: &Args) -> Result<(), Failure> {
+    args.expect(2, 2, &["ttl"])?;
+    let value = match args.positional[1].as_str() {
+        "-" => {
+            let mut value = Vec::new();
+            io::stdin().read_to_end(&mut value)?;
+            value
+        }
+        value => value.as_bytes().to_vec(),
+    };
+    let mut kv = open(false)?;
+    let key = args.positional[0].as_str();
+    match args.ttl {
+        Some(secs) => kv.set_with_ttl(key, value, Duration::from_secs(secs))?,
+        None => kv.set(key, value)?,
+    }
+    kv.close()?;
+    println!("{{\"ok\":true}}");
+    Ok(())
+}
+
+fn rm(args: &Args) -> Result<(), Failure> {
+    args.expect(1, 1, &[])?;
+    let mut kv = open(false)?;
+    let key = args.positional[0].as_str();
+    if kv.get(key)?.is_none() {
+        return Err(Failure::NotFound);
+    }
+    kv.remove(key)?;
+    kv.close()?;
+    println!("{{\"ok\":true}}");
+    Ok(())
+}
+
+/// 每行输出一个条目；BTree 索引按 Key 升序，Hash 索引下遍历全部条目再按前缀过滤
+fn scan(args: &Args) -> Result<(), Failure> {
+    args.expect(0, 0, &["prefix", "limit"])?;
+    let kv = open(true)?;
+    let prefix = args.prefix.as_deref().unwrap_or_default().as_bytes();
+    let entries: Box<dyn Iterator<Item = Result<LogEntry, TitaniumError>>> =
+        match kv.scan_prefix(prefix) {
+            Ok(iter) => Box::new(iter.filter_map(|e| e.entry().transpose())),
+            Err(TitaniumError::Unsupported(_)) => Box::new(
+                kv.iter()
+                    .filter(|e| e.as_ref().map_or(true, |e| e.key.starts_with(prefix))),
+            ),
+            Err(e) => return Err(e.into()),
+        };
+    let mut stdout = io::stdout().lock();
+    for entry in entries.take(args.limit.unwrap_or(usize::MAX)) {
+        writeln!(stdout, "{}", entry_json(&entry?))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn stats(args: &Args) -> Result<(), Failure> {
+    args.expect(0, 0, &[])?;
+    let kv = open(true)?;
+    println!("{}", kv.stats()?.to_json());
+    Ok(())
+}
+
+fn compact(args: &Args) -> Result<(), Failure> {
+    args.expect(0, 0, &[])?;
+    let mut kv = open(false)?;
+    let before = kv.stats()?;
+    kv.compact()?;
+    let after = kv.stats()?;
+    kv.close()?;
+    println!(
+        "{{\"before\":{},\"after\":{}}}",
+        before.to_json(),
+        after.to_json()
+    );
+    Ok(())
+}
+
+/// 条目的 JSON 表示：ttl 为剩余秒数 (没有 TTL 时为 null)，version 为序列号
+fn entry_json(entry: &LogEntry) -> String {
+    let ttl = match entry.expire_at() {
+        Some(expire_at) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            expire_at.saturating_sub(now).div_ceil(1000).to_string()
+        }
+        None => "null".to_string(),
+    };
+    format!(
+        "{{{},{},\"ttl\":{},\"version\":{}}}",
+        bytes_field("key", &entry.key),
+        bytes_field("value", &entry.value),
+        ttl,
+        entry.sequence_number
+    )
+}
+
+/// UTF-8 内容输出为 `"name":"..."`，二进制内容输出为 `"name_base64":"..."`
+fn bytes_field(name: &str, bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => format!("\"{}\":{}", name, json_string(s)),
+        Err(_) => format!("\"{}_base64\":\"{}\"", name, base64_encode(bytes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--data-dir", "/tmp/db", "set", "k", "v", "--ttl=60"]).unwrap();
+        assert_eq!(args.command.as_deref(), Some("set"));
+        assert_eq!(args.positional, vec!["k", "v"]);
+        assert_eq!(args.data_dir.as_deref(), Some("/tmp/db"));
+        assert_eq!(args.ttl, Some(60));
+        assert_eq!(args.config_file(), DEFAULT_CONFIG_FILE);
+        assert!(args.expect(2, 2, &["ttl"]).is_ok());
+        assert!(args.expect(2, 2, &[]).is_err());
+        assert!(args.expect(1, 1, &["ttl"]).is_err());
+
+        // `--` 之后以 `--` 开头的参数也是位置参数
+        let args = parse(&["set", "--config", "a.conf", "--", "k", "--v"]).unwrap();
+        assert_eq!(args.positional, vec!["k", "--v"]);
+        assert_eq!(args.config_file(), "a.conf");
+
+        let args = parse(&["scan", "--prefix", "user:", "--limit", "5", "-h"]).unwrap();
+        assert_eq!(args.prefix.as_deref(), Some("user:"));
+        assert_eq!(args.limit, Some(5));
+        assert!(args.help);
+
+        assert!(parse(&["get", "k", "--nope"]).is_err());
+        assert!(parse(&["set", "k", "v", "--ttl"]).is_err());
+        assert!(parse(&["set", "k", "v", "--ttl", "0"]).is_err());
+        assert!(parse(&["set", "k", "v", "--ttl", "18446744073709551615"]).is_err());
+        let max = MAX_TTL.as_secs().to_string();
+        assert_eq!(
+            parse(&["set", "k", "v", "--ttl", &max]).unwrap().ttl,
+            Some(MAX_TTL.as_secs())
+        );
+        let over = (MAX_TTL.as_secs() + 1).to_string();
+        assert!(parse(&["set", "k", "v", "--ttl", &over]).is_err());
+        assert_eq!(parse(&[]).unwrap(), Args::default());
+    }
+
+    #[test]
+    fn test_entry_json() {
+        let entry = LogEntry::new("k\"1", b"v".to_vec(), 7).build();
+        assert_eq!(
+            entry_json(&entry),
+            "{\"key\":\"k\\\"1\",\"value\":\"v\",\"ttl\":null,\"version\":7}"
+        );
+        assert_eq!(
+            bytes_field("value", b"\xff\x00"),
+            "\"value_base64\":\"/wA=\""
+        );
+    }
+}
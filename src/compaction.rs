@@ -1,33 +1,573 @@
 use std::{
-    collections::HashMap,
-    io::{self, BufReader, BufWriter, Read, Seek, Write},
-    path::Path,
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    io::{BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    config,
     error::TitaniumError,
+    hint::{self, HintEntry, HintWriter},
     index::LogIndex,
-    kv::{self, FileAtReader},
-    log_entry::Decoder,
-    storage::{FileSystem, RandomAccessFile, Storage},
-    utils::{decode_varint, encode_varint},
+    kv::{DataFile, FileAtReader, KeyDir},
+    log_entry::{CURRENT_FORMAT_VERSION, Decoder, FileHeader},
+    storage::{FileSystem, Storage},
     writer::Writer,
 };
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+/// 合并清单文件名：存在即表示所有合并文件均已落盘，替换阶段可以安全重放
+pub const MERGE_MANIFEST: &str = "MERGE";
+/// 合并进度文件名：记录最近一次合并覆盖到的最大文件 ID，重启后据此统计待合并的归档文件
+pub const MERGE_PROGRESS: &str = "MERGED";
+/// 合并过程中临时文件的后缀 (如 0001.bs.merge)
+const MERGE_SUFFIX: &str = "merge";
+/// 原子写入 (写临时文件再 rename) 使用的临时文件后缀，如 MERGE.tmp、0001.hbs.tmp
+const TMP_SUFFIX: &str = "tmp";
 
-struct Compacter;
+pub struct Compacter;
+
+/// 一次合并的结果：文件已经替换完成，等待更新内存中的 file_map、索引和版本历史
+pub(crate) struct Merge {
+    input_ids: Vec<u32>,
+    files: Vec<(u32, DataFile)>,
+    // 索引需要更新的条目、因过期被丢弃的当前版本、版本历史中旧版本的新位置 (None 表示已过期丢弃)
+    index_updates: Vec<(Vec<u8>, LogIndex)>,
+    expired_keys: Vec<Vec<u8>>,
+    history_moves: HashMap<(u32, u64), Option<LogIndex>>,
+}
 
 // 通过对比索引offset来判断是否时最新的版本
 // 采用流式模式防止双倍内存占用问题，生成一个hint文件用户快速构建hashmap
 // 只有在最后替换的时候占用写锁，hashmap采用读写锁保护
 impl Compacter {
-    pub fn compact(
-        file_map: &HashMap<u32, (Box<dyn RandomAccessFile>, PathBuf)>,
-        kv_store: &mut kv::KVStore,
+    /// 合并所有归档文件 (file_map)，只保留索引仍然指向的条目。
+    ///
+    /// 流程：
+    /// 1. 流式扫描归档文件，将存活条目写入 `NNNN.bs.merge` 临时文件。
+    ///    新文件复用旧文件中最小的若干个 ID，保证它们依然排在活跃文件之前，restore 的重放顺序不变。
+    ///    旧文件按各自的格式版本解码，新文件总是以当前格式写入。
+    /// 2. 所有临时文件 sync 后，原子地写入合并清单 (MERGE)，这是合并的提交点。
+    /// 3. 按清单将临时文件 rename 覆盖旧文件，删除多余的旧文件，最后删除清单。
+    /// 4. 打开合并后的文件并生成 Hint 文件，返回的 [`Merge`] 由 [`Compacter::install`] 替换到内存中。
+    ///
+    /// 只需要 KeyDir 的读权限：已打开的旧文件句柄在替换后仍然可读，读者在 install 之前继续使用它们。
+    /// 调用者需要阻止并发写入 (索引在合并期间不能变化)，并先按活跃快照裁剪版本历史。
+    ///
+    /// 在提交点之前崩溃，临时文件会在下次启动时被丢弃；之后崩溃，则由 [`Compacter::recover`] 完成替换。
+    pub(crate) fn merge(kv: &KeyDir) -> Result<Option<Merge>, TitaniumError> {
+        let mut input_ids: Vec<u32> = kv.file_map.keys().cloned().collect();
+        if input_ids.is_empty() {
+            return Ok(None);
+        }
+        input_ids.sort();
+
+        let data_path = kv.data_path.clone();
+        let max_file_size = kv.config.max_file_size() as u64;
+        let (max_key, max_val) = kv.config.max_sizes();
+        let mut decoder = Decoder::new(max_key, max_val);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+
+        let mut output_ids: Vec<u32> = Vec::new();
+        let mut writer: Option<Writer<Box<dyn Storage>>> = None;
+        // 记录被搬迁条目的新位置 (输出文件 ID, Hint 记录)，在替换完成后统一更新索引
+        let mut relocated: Vec<(u32, HintEntry)> = Vec::new();
+        // 索引需要更新的条目、因过期被丢弃的当前版本、版本历史中旧版本的新位置 (None 表示已过期丢弃)
+        let mut index_updates: Vec<(Vec<u8>, LogIndex)> = Vec::new();
+        let mut expired_keys: Vec<Vec<u8>> = Vec::new();
+        let mut history_moves: HashMap<(u32, u64), Option<LogIndex>> = HashMap::new();
+
+        // 活跃快照仍需要的旧版本也必须保留
+        let history_locations: HashSet<(u32, u64)> = kv
+            .history
+            .locations()
+            .map(|index| (index.file_id, index.offset))
+            .collect();
+
+        let result = (|| -> Result<(), TitaniumError> {
+            for &file_id in &input_ids {
+                let input = &kv.file_map[&file_id];
+                decoder.set_version(input.version);
+                let mut reader = BufReader::new(FileAtReader {
+                    reader: input.reader.as_ref(),
+                    offset: FileHeader::data_offset(input.version),
+                });
+
+                loop {
+                    let offset = reader.stream_position()?;
+                    let mut entry = match decoder.decode_from(&mut reader)? {
+                        Some(entry) => entry,
+                        None => break,
+                    };
+
+                    // 只有索引仍指向该位置的条目才是最新版本；墓碑和被覆盖的旧值都不在索引中
+                    let in_index = kv
+                        .indexer
+                        .get(&entry.key)
+                        .is_some_and(|idx| idx.file_id == file_id && idx.offset == offset);
+                    let in_history = history_locations.contains(&(file_id, offset));
+                    // 快照保留了被删除 Key 的旧版本时，墓碑也必须保留，否则重启后旧版本会复活
+                    let keep_tombstone =
+                        entry.is_tombstone() && kv.history.contains_key(&entry.key);
+                    if !in_index && !in_history && !keep_tombstone {
+                        continue;
+                    }
+                    if entry.expire_at().is_some_and(|ts| now > ts) {
+                        if in_index {
+                            expired_keys.push(entry.key);
+                        } else if in_history {
+                            history_moves.insert((file_id, offset), None);
+                        }
+                        continue;
+                    }
+
+                    // 当前输出文件写满时切换到下一个 ID；ID 用完后继续写入最后一个文件
+                    let need_new_file = match &writer {
+                        None => true,
+                        Some(w) => {
+                            w.current_offset() >= max_file_size
+                                && output_ids.len() < input_ids.len()
+                        }
+                    };
+                    if need_new_file {
+                        if let Some(mut w) = writer.take() {
+                            w.sync()?;
+                        }
+                        let out_id = input_ids[output_ids.len()];
+                        let file = kv.fs.create_file(&merge_path(&data_path, out_id))?;
+                        writer = Some(Writer::create(file)?);
+                        output_ids.push(out_id);
+                    }
+
+                    // 已提交的批次条目作为独立条目写出，不再需要提交标记
+                    entry.clear_batch();
+                    let w = writer.as_mut().unwrap();
+                    let new_offset = w.write_entry(&entry)?;
+                    let out_id = *output_ids.last().unwrap();
+                    let new_index = LogIndex::new(out_id, new_offset, entry.value.len() as u32);
+                    if in_index {
+                        index_updates.push((entry.key.clone(), new_index));
+                    }
+                    if in_history {
+                        history_moves.insert((file_id, offset), Some(new_index));
+                    }
+                    relocated.push((
+                        out_id,
+                        HintEntry {
+                            offset: new_offset,
+                            val_len: entry.value.len() as u32,
+                            sequence_number: entry.sequence_number,
+                            expire_at: entry.expire_at(),
+                            tombstone: entry.is_tombstone(),
+                            key: entry.key,
+                        },
+                    ));
+                }
+            }
+
+            if let Some(mut w) = writer.take() {
+                w.sync()?;
+            }
+
+            let obsolete_ids = &input_ids[output_ids.len()..];
+            Self::write_manifest(kv.fs.as_ref(), &data_path, &output_ids, obsolete_ids)
+        })();
+
+        if let Err(e) = result {
+            // 提交点之前失败：丢弃临时文件，旧文件保持不变
+            for &id in &output_ids {
+                let _ = kv.fs.remove_file(&merge_path(&data_path, id));
+            }
+            return Err(e);
+        }
+
+        let obsolete_ids = &input_ids[output_ids.len()..];
+        Self::apply_manifest(kv.fs.as_ref(), &data_path, &output_ids, obsolete_ids)?;
+
+        // 重新打开合并后的文件，旧句柄在 install 时才丢弃
+        let mut files = Vec::with_capacity(output_ids.len());
+        for &id in &output_ids {
+            let path = data_path.join(format!("{:04}.bs", id));
+            let reader = kv.fs.open_reader(&path)?;
+            files.push((
+                id,
+                DataFile {
+                    reader,
+                    path,
+                    version: CURRENT_FORMAT_VERSION,
+                },
+            ));
+        }
+
+        // Hint 只是启动加速手段，写入失败不影响合并结果
+        if let Err(e) = Self::write_hints(kv, &files, &relocated) {
+            eprintln!("Compaction: Failed to write hint files: {}", e);
+        }
+
+        Ok(Some(Merge {
+            input_ids,
+            files,
+            index_updates,
+            expired_keys,
+            history_moves,
+        }))
+    }
+
+    /// 把合并结果替换到内存中：file_map、索引和版本历史
+    pub(crate) fn install(kv: &mut KeyDir, merge: Merge) {
+        // 替换 file_map：旧句柄全部丢弃，换成合并后的文件
+        for id in &merge.input_ids {
+            kv.file_map.remove(id);
+        }
+        kv.file_map.extend(merge.files);
+
+        for (key, index) in merge.index_updates {
+            kv.indexer.put(key, index);
+        }
+        // 过期条目已被丢弃，索引不能再指向被替换的文件
+        for key in merge.expired_keys {
+            kv.indexer.remove(&key);
+        }
+        let history_moves = merge.history_moves;
+        kv.history
+            .relocate(|index| history_moves.get(&(index.file_id, index.offset)).copied());
+    }
+
+    fn write_hints(
+        kv: &KeyDir,
+        files: &[(u32, DataFile)],
+        relocated: &[(u32, HintEntry)],
     ) -> Result<(), TitaniumError> {
+        let mut entries = relocated.iter().peekable();
+        for (id, file) in files {
+            let id = *id;
+            let data_len = file.reader.len()?;
+            let mut writer = HintWriter::create(kv.fs.as_ref(), &kv.data_path, id, data_len)?;
+            while let Some((_, entry)) = entries.next_if(|(file_id, _)| *file_id == id) {
+                writer.append(entry)?;
+            }
+            writer.finish(kv.fs.as_ref())?;
+        }
         Ok(())
     }
+
+    /// 启动时调用：处理上一次合并遗留的状态
+    ///
+    /// - 合并清单存在：合并已提交，继续完成 rename 和删除。
+    /// - 合并清单不存在：合并未完成，删除所有临时文件。
+    ///
+    /// 同时清理其他原子写入 (如 Hint 文件) 遗留的 `.tmp` 文件。
+    pub fn recover(fs: &dyn FileSystem, data_path: &Path) -> Result<(), TitaniumError> {
+        let manifest_path = data_path.join(MERGE_MANIFEST);
+        if fs.exists(&manifest_path) {
+            let (output_ids, obsolete_ids) = Self::read_manifest(fs, &manifest_path)?;
+            eprintln!(
+                "Recover: Found merge manifest, finishing compaction of {} files.",
+                output_ids.len() + obsolete_ids.len()
+            );
+            Self::apply_manifest(fs, data_path, &output_ids, &obsolete_ids)?;
+        }
+
+        for path in fs.list_files(data_path)? {
+            let is_temp = path
+                .extension()
+                .is_some_and(|ext| ext == MERGE_SUFFIX || ext == TMP_SUFFIX);
+            if is_temp {
+                fs.remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 清单格式 (文本)：第一行为合并输出的文件 ID，第二行为需要删除的旧文件 ID，以空格分隔
+    fn write_manifest(
+        fs: &dyn FileSystem,
+        data_path: &Path,
+        output_ids: &[u32],
+        obsolete_ids: &[u32],
+    ) -> Result<(), TitaniumError> {
+        let join = |ids: &[u32]| {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let content = format!("{}\n{}\n", join(output_ids), join(obsolete_ids));
+
+        // 先写临时文件再 rename，保证清单要么完整存在，要么不存在
+        write_atomic(fs, data_path, MERGE_MANIFEST, &content)
+    }
+
+    /// 读取合并进度：最近一次合并的输入中最大的文件 ID
+    ///
+    /// 合并总是覆盖所有归档文件，因此只有 ID 更大的归档文件是之后封存、尚未合并的。
+    /// 没有进度文件 (从未合并) 或无法解析时返回 None，此时所有归档文件都视为待合并。
+    pub(crate) fn read_progress(fs: &dyn FileSystem, data_path: &Path) -> Option<u32> {
+        let path = data_path.join(MERGE_PROGRESS);
+        let file = fs.open_reader(&path).ok()?;
+        let mut content = String::new();
+        FileAtReader {
+            reader: file.as_ref(),
+            offset: 0,
+        }
+        .read_to_string(&mut content)
+        .ok()?;
+        match content.trim().parse() {
+            Ok(id) => Some(id),
+            Err(e) => {
+                eprintln!(
+                    "Open: Ignoring invalid merge progress file {:?}: {}",
+                    path, e
+                );
+                None
+            }
+        }
+    }
+
+    fn read_manifest(
+        fs: &dyn FileSystem,
+        manifest_path: &Path,
+    ) -> Result<(Vec<u32>, Vec<u32>), TitaniumError> {
+        let file = fs.open_reader(manifest_path)?;
+        let mut content = String::new();
+        FileAtReader {
+            reader: file.as_ref(),
+            offset: 0,
+        }
+        .read_to_string(&mut content)?;
+
+        let parse = |line: Option<&str>| -> Result<Vec<u32>, TitaniumError> {
+            line.unwrap_or("")
+                .split_whitespace()
+                .map(|s| {
+                    s.parse::<u32>().map_err(|e| {
+                        TitaniumError::Io(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Invalid merge manifest entry '{}': {}", s, e),
+                        ))
+                    })
+                })
+                .collect()
+        };
+        let mut lines = content.lines();
+        let output_ids = parse(lines.next())?;
+        let obsolete_ids = parse(lines.next())?;
+        Ok((output_ids, obsolete_ids))
+    }
+
+    /// 替换阶段：可重入，重复执行结果一致
+    fn apply_manifest(
+        fs: &dyn FileSystem,
+        data_path: &Path,
+        output_ids: &[u32],
+        obsolete_ids: &[u32],
+    ) -> Result<(), TitaniumError> {
+        // 先删除旧的 Hint 文件，防止它们与替换后的数据文件错配
+        for &id in output_ids.iter().chain(obsolete_ids) {
+            let path = hint::hint_path(data_path, id);
+            if fs.exists(&path) {
+                fs.remove_file(&path)?;
+            }
+        }
+        for &id in output_ids {
+            let tmp = merge_path(data_path, id);
+            if fs.exists(&tmp) {
+                fs.rename(&tmp, &data_path.join(format!("{:04}.bs", id)))?;
+            }
+        }
+        for &id in obsolete_ids {
+            let path = data_path.join(format!("{:04}.bs", id));
+            if fs.exists(&path) {
+                fs.remove_file(&path)?;
+            }
+        }
+        // 删除清单之前记录合并进度，崩溃后重放清单时会再次写入
+        if let Some(last_id) = output_ids.iter().chain(obsolete_ids).max() {
+            write_atomic(fs, data_path, MERGE_PROGRESS, &format!("{}\n", last_id))?;
+        }
+        fs.remove_file(&data_path.join(MERGE_MANIFEST))?;
+        Ok(())
+    }
+}
+
+fn merge_path(data_path: &Path, id: u32) -> PathBuf {
+    data_path.join(format!("{:04}.bs.{}", id, MERGE_SUFFIX))
+}
+
+/// 原子地写入 data_path 下的小文件：先写 `<name>.tmp` 并 sync，再 rename 覆盖
+fn write_atomic(
+    fs: &dyn FileSystem,
+    data_path: &Path,
+    name: &str,
+    content: &str,
+) -> Result<(), TitaniumError> {
+    let tmp_path = data_path.join(format!("{}.{}", name, TMP_SUFFIX));
+    let mut file = fs.create_file(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync()?;
+    fs.rename(&tmp_path, &data_path.join(name))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::kv::KVStore;
+    use crate::log_entry::LogEntry;
+    use crate::storage::MemFileSystem;
+    use std::sync::Arc;
+
+    fn create_watcher(data_dir: &str, max_file_size: usize) -> config::ConfigWatcher {
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = data_dir.to_string();
+        cfg.max_file_size = max_file_size;
+        // 关闭自动合并，由测试显式触发
+        cfg.compaction_threshold = usize::MAX;
+        watcher.override_config(cfg);
+        watcher
+    }
+
+    fn data_files(fs: &MemFileSystem, dir: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs
+            .list_files(Path::new(dir))
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|ext| ext == "bs"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_compact_drops_stale_entries() {
+        let path = "test_compact_stale";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = create_watcher(path, 100);
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            for round in 0..5 {
+                for i in 0..4 {
+                    let value = format!("value-{}-{}", i, round);
+                    kv.set(format!("key{}", i), value.into_bytes()).unwrap();
+                }
+            }
+            kv.remove("key3").unwrap();
+            // 确保所有数据都已归档
+            kv.set("last".to_string(), b"x".to_vec()).unwrap();
+
+            let before = data_files(&fs, path).len();
+            kv.compact().unwrap();
+            let after = data_files(&fs, path).len();
+            assert!(
+                after < before,
+                "expected fewer files: {} -> {}",
+                before,
+                after
+            );
+
+            for i in 0..3 {
+                let entry = kv.get(format!("key{}", i)).unwrap().unwrap();
+                assert_eq!(entry.value, format!("value-{}-4", i).into_bytes());
+            }
+            assert!(kv.get("key3").unwrap().is_none());
+        }
+
+        // 合并后的数据目录可以正常恢复
+        let mut kv = KVStore::new(watcher, fs.clone()).unwrap();
+        kv.restore().unwrap();
+        for i in 0..3 {
+            let entry = kv.get(format!("key{}", i)).unwrap().unwrap();
+            assert_eq!(entry.value, format!("value-{}-4", i).into_bytes());
+        }
+        assert!(kv.get("key3").unwrap().is_none());
+        assert!(kv.get("last").unwrap().is_some());
+        assert!(!fs.exists(&Path::new(path).join(MERGE_MANIFEST)));
+        assert!(fs.exists(&hint::hint_path(Path::new(path), 1)));
+    }
+
+    #[test]
+    fn test_compact_removes_expired_from_index() {
+        let path = "test_compact_expired";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = create_watcher(path, 100);
+        let mut kv = KVStore::new(watcher, fs.clone()).unwrap();
+
+        kv.set_with_ttl(
+            "ttl",
+            b"short-lived".to_vec(),
+            std::time::Duration::from_millis(1),
+        )
+        .unwrap();
+        for i in 0..10 {
+            kv.set(format!("key{}", i), format!("value-{}", i).into_bytes())
+                .unwrap();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        kv.compact().unwrap();
+
+        // 过期条目所在的文件已被替换，读取不能落到新文件的其他条目上
+        assert!(kv.get("ttl").unwrap().is_none());
+        for i in 0..10 {
+            let entry = kv.get(format!("key{}", i)).unwrap().unwrap();
+            assert_eq!(entry.value, format!("value-{}", i).into_bytes());
+        }
+    }
+
+    #[test]
+    fn test_recover_discards_uncommitted_merge() {
+        let path = "test_compact_uncommitted";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = create_watcher(path, 1024);
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.set("k1".to_string(), b"v1".to_vec()).unwrap();
+        }
+
+        // 模拟合并中途崩溃：只有临时文件，没有清单
+        let tmp = merge_path(Path::new(path), 1);
+        fs.create_file(&tmp).unwrap();
+
+        let mut kv = KVStore::new(watcher, fs.clone()).unwrap();
+        kv.restore().unwrap();
+        assert!(!fs.exists(&tmp));
+        assert_eq!(kv.get("k1").unwrap().unwrap().value, b"v1");
+    }
+
+    #[test]
+    fn test_recover_finishes_committed_merge() {
+        let path = "test_compact_committed";
+        let dir = Path::new(path);
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = create_watcher(path, 1024);
+
+        // 旧文件：0001.bs 与 0002.bs 中都是过期版本
+        for (id, value) in [(1, b"old1"), (2, b"old2")] {
+            let mut file = fs.create_file(&dir.join(format!("{:04}.bs", id))).unwrap();
+            let entry = LogEntry::new("k".to_string(), value.to_vec(), id as u64).build();
+            entry.encode_to(&mut file).unwrap();
+        }
+        // 合并输出已落盘且清单已提交，但尚未完成替换
+        {
+            let mut file = fs.create_file(&merge_path(dir, 1)).unwrap();
+            let entry = LogEntry::new("k".to_string(), b"merged".to_vec(), 2).build();
+            entry.encode_to(&mut file).unwrap();
+        }
+        Compacter::write_manifest(fs.as_ref(), dir, &[1], &[2]).unwrap();
+        // 活跃文件
+        fs.create_file(&dir.join("0003.bs")).unwrap();
+
+        let mut kv = KVStore::new(watcher, fs.clone()).unwrap();
+        kv.restore().unwrap();
+
+        assert!(!fs.exists(&dir.join(MERGE_MANIFEST)));
+        assert!(!fs.exists(&merge_path(dir, 1)));
+        assert!(!fs.exists(&dir.join("0002.bs")));
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"merged");
+    }
 }
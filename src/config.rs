@@ -1,18 +1,20 @@
 use crate::error::TitaniumError;
+use crate::index::IndexerKind;
 use std::fs;
-use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, OnceLock, RwLock};
 use std::thread;
 use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WriteMod {
     // 立即同步到磁盘
     Sync,
     // 等待缓冲区满之后再自动刷新到磁盘
     Buffer,
+    // 每次写入推给内核，后台线程每隔指定毫秒数同步一次到磁盘，丢失窗口不超过该间隔
+    Interval(u64),
 }
 
 pub const DEFAULT_CONFIG_FILE: &str = "titanium.conf";
@@ -20,12 +22,17 @@ pub const DEFAULT_DATA_DIR_PATH: &str = "./data";
 pub const DEFAULT_MAX_KEY_SIZE: usize = 1024; // 1 KB
 pub const DEFAULT_MAX_VALUE_SIZE: usize = 10 * 1024 * 1024; // 10 MB
 pub const DEFAULT_WRITE_MOD: WriteMod = WriteMod::Sync;
+pub const DEFAULT_SYNC_INTERVAL_MS: u64 = 1000; // 1 second
 pub const DEFAULT_MAX_FILE_SIZE: usize = 1073741824; // 1GB
 pub const DEFAULT_COMPACTION_THRESHOLD: usize = 4;
 pub const DEFAULT_WRITE_STALL_THRESHOLD: usize = 8;
 pub const DEFAULT_WRITE_STOP_THRESHOLD: usize = 20;
 pub const DEFAULT_COMPACTION_CHECK_INTERVAL_MS: u64 = 60_000; // 1 minute
 pub const DEFAULT_MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024; // 1 GB
+pub const DEFAULT_INDEXER: IndexerKind = IndexerKind::Hash;
+pub const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:6379";
+pub const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:8080";
+pub const DEFAULT_UNIX_SOCKET_PERM: u32 = 0o700;
 static GLOBAL_WATCHER: OnceLock<ConfigWatcher> = OnceLock::new();
 
 #[derive(Debug, Clone)]
@@ -40,6 +47,15 @@ pub struct Config {
     pub write_stop_threshold: usize,
     pub compaction_check_interval_ms: u64,
     pub min_free_space: u64,
+    pub indexer: IndexerKind,
+    /// `serve` 子命令 (RESP 服务) 的监听地址
+    pub server_addr: String,
+    /// `serve-http` 子命令 (HTTP/JSON 接口) 的监听地址
+    pub http_addr: String,
+    /// RESP 服务的 Unix domain socket 路径，None 表示不监听
+    pub unix_socket: Option<String>,
+    /// Unix domain socket 文件的权限位
+    pub unix_socket_perm: u32,
 }
 
 impl Config {
@@ -55,6 +71,11 @@ impl Config {
             write_stop_threshold: DEFAULT_WRITE_STOP_THRESHOLD,
             compaction_check_interval_ms: DEFAULT_COMPACTION_CHECK_INTERVAL_MS,
             min_free_space: DEFAULT_MIN_FREE_SPACE,
+            indexer: DEFAULT_INDEXER,
+            server_addr: DEFAULT_SERVER_ADDR.to_string(),
+            http_addr: DEFAULT_HTTP_ADDR.to_string(),
+            unix_socket: None,
+            unix_socket_perm: DEFAULT_UNIX_SOCKET_PERM,
         }
     }
 
@@ -68,6 +89,12 @@ impl Config {
         if self.max_file_size == 0 {
             return Err("max_file_size must be greater than 0".to_string());
         }
+        if self.write_mod == WriteMod::Interval(0) {
+            return Err("sync_interval_ms must be greater than 0".to_string());
+        }
+        if self.unix_socket_perm > 0o777 {
+            return Err("unix_socket_perm must be an octal mode no greater than 777".to_string());
+        }
         if self.write_stop_threshold <= self.write_stall_threshold {
             return Err(
                 "write_stop_threshold must be greater than write_stall_threshold".to_string(),
@@ -83,6 +110,8 @@ impl Config {
 
         let content = fs::read_to_string(path)?;
         let mut config = Self::default();
+        // sync_interval_ms 可能出现在 write_mod 之前，解析完成后再合并
+        let mut sync_interval_ms = DEFAULT_SYNC_INTERVAL_MS;
         for line in content.lines() {
             let line = line.trim();
             if line.is_empty() || line.starts_with('#') {
@@ -110,6 +139,7 @@ impl Config {
                     "write_mod" => match value.trim().to_lowercase().as_str() {
                         "sync" => config.write_mod = WriteMod::Sync,
                         "buffer" => config.write_mod = WriteMod::Buffer,
+                        "interval" => config.write_mod = WriteMod::Interval(sync_interval_ms),
                         unknown => {
                             return Err(TitaniumError::ConfigError(format!(
                                 "Unknown write_mod variant: '{}'",
@@ -117,6 +147,24 @@ impl Config {
                             )));
                         }
                     },
+                    "sync_interval_ms" => {
+                        sync_interval_ms = value.trim().parse().map_err(|e| {
+                            TitaniumError::ConfigError(format!(
+                                "Invalid sync_interval_ms '{}': {}",
+                                value, e
+                            ))
+                        })?;
+                    }
+                    "indexer" => match value.trim().to_lowercase().as_str() {
+                        "hash" => config.indexer = IndexerKind::Hash,
+                        "btree" => config.indexer = IndexerKind::BTree,
+                        unknown => {
+                            return Err(TitaniumError::ConfigError(format!(
+                                "Unknown indexer variant: '{}'",
+                                unknown
+                            )));
+                        }
+                    },
                     "compaction_threshold" => {
                         config.compaction_threshold = value.trim().parse().map_err(|e| {
                             TitaniumError::ConfigError(format!(
@@ -150,6 +198,22 @@ impl Config {
                                 ))
                             })?;
                     }
+                    "server_addr" => config.server_addr = value.trim().to_string(),
+                    "http_addr" => config.http_addr = value.trim().to_string(),
+                    "unix_socket" => {
+                        let path = value.trim();
+                        config.unix_socket = (!path.is_empty()).then(|| path.to_string());
+                    }
+                    "unix_socket_perm" => {
+                        let perm = value.trim();
+                        let digits = perm.strip_prefix("0o").unwrap_or(perm);
+                        config.unix_socket_perm = u32::from_str_radix(digits, 8).map_err(|e| {
+                            TitaniumError::ConfigError(format!(
+                                "Invalid unix_socket_perm '{}': {}",
+                                value, e
+                            ))
+                        })?;
+                    }
                     "min_free_space" => {
                         config.min_free_space = value.trim().parse().map_err(|e| {
                             TitaniumError::ConfigError(format!(
@@ -162,6 +226,9 @@ impl Config {
                 }
             }
         }
+        if let WriteMod::Interval(ms) = &mut config.write_mod {
+            *ms = sync_interval_ms;
+        }
 
         if let Err(e) = config.validate() {
             return Err(TitaniumError::ConfigError(e));
@@ -273,6 +340,17 @@ impl ConfigWatcher {
         guard.max_file_size
     }
 
+    pub fn min_free_space(&self) -> u64 {
+        let guard = self.inner.read().expect("Config lock poisoned");
+        guard.min_free_space
+    }
+
+    /// 轻量级获取写入背压阈值 (stall, stop)
+    pub fn write_thresholds(&self) -> (usize, usize) {
+        let guard = self.inner.read().expect("Config lock poisoned");
+        (guard.write_stall_threshold, guard.write_stop_threshold)
+    }
+
     /// 停止后台监控线程
     pub fn stop(&self) {
         self.running.store(false, Ordering::Relaxed);
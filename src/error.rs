@@ -11,6 +11,18 @@ pub enum TitaniumError {
     #[error("Varint Decode Error")]
     VarintDecodeError,
 
+    #[error("Unsupported data file format version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Unsupported Operation: {0}")]
+    Unsupported(&'static str),
+
+    #[error("Data directory {0:?} is locked: it is already opened by another process or KVStore")]
+    DirectoryLocked(std::path::PathBuf),
+
+    #[error("Read Only: the store was opened in read-only mode")]
+    ReadOnly,
+
     #[error("Config Error: {0}")]
     ConfigError(String),
 
@@ -19,4 +31,28 @@ pub enum TitaniumError {
 
     #[error("Disk Full: available space {available} is less than required {required}")]
     DiskFull { available: u64, required: u64 },
+
+    #[error(
+        "Transaction Conflict: key '{}' was modified after it was read",
+        String::from_utf8_lossy(.key)
+    )]
+    TransactionConflict { key: Vec<u8> },
+
+    #[error("Transaction Store Mismatch: the transaction was started on a different store")]
+    TransactionStoreMismatch,
+}
+
+impl TitaniumError {
+    /// 是否为数据损坏类错误 (CRC 错、Varint 错、意外 EOF、数据超长)
+    /// 恢复流程遇到这类错误时截断/丢弃损坏的尾部，其他错误则直接向上返回
+    pub fn is_corruption(&self) -> bool {
+        match self {
+            TitaniumError::CrcMismatch { .. } | TitaniumError::VarintDecodeError => true,
+            TitaniumError::Io(io_e) => matches!(
+                io_e.kind(),
+                std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData
+            ),
+            _ => false,
+        }
+    }
 }
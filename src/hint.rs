@@ -0,0 +1,358 @@
+use crate::{
+    batch::BatchReplay,
+    error::TitaniumError,
+    kv::FileAtReader,
+    log_entry::{Decoder, FileHeader, LogHeader},
+    storage::{FileSystem, RandomAccessFile, Storage},
+    utils::{decode_varint, encode_varint},
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Hint 文件魔数
+const HINT_MAGIC: &[u8; 4] = b"TIHT";
+
+/// 记录标志位，与 EntryType 的位定义保持一致
+const FLAG_TOMBSTONE: u8 = 1 << 0;
+const FLAG_TTL: u8 = 1 << 2;
+
+/// Hint 文件路径：与数据文件同名，扩展名为 .hbs (如 0001.bs -> 0001.hbs)
+pub fn hint_path(data_path: &Path, file_id: u32) -> PathBuf {
+    data_path.join(format!("{:04}.hbs", file_id))
+}
+
+/// Hint 记录：数据文件中一个条目的索引信息，不包含 Value
+///
+/// 文件布局：
+/// Header: Magic(4B) | DataFileLen(8B, LE)
+/// Record: CRC(4B) | Flags(1B) | SeqNo(varint) | Offset(varint) | VLen(varint) | KLen(varint) | [ExpireAt(varint)] | Key
+///
+/// DataFileLen 用于校验 Hint 是否与数据文件匹配，长度不一致时视为失效。
+#[derive(Debug, PartialEq)]
+pub struct HintEntry {
+    pub key: Vec<u8>,
+    pub offset: u64,
+    pub val_len: u32,
+    pub sequence_number: u64,
+    pub expire_at: Option<u64>,
+    pub tombstone: bool,
+}
+
+impl HintEntry {
+    pub fn from_header(header: LogHeader, offset: u64) -> Self {
+        Self {
+            tombstone: header.is_tombstone(),
+            key: header.key,
+            offset,
+            val_len: header.val_len,
+            sequence_number: header.sequence_number,
+            expire_at: header.expire_at,
+        }
+    }
+
+    /// 将元数据 (Flags ~ ExpireAt) 编码到栈上缓冲区，返回写入长度
+    fn encode_meta(&self, buf: &mut [u8; 64]) -> usize {
+        // Flags(1) + SeqNo(10) + Offset(10) + VLen(5) + KLen(5) + ExpireAt(10) = 41 bytes
+        let mut offset = 0;
+
+        let mut flags = 0u8;
+        if self.tombstone {
+            flags |= FLAG_TOMBSTONE;
+        }
+        if self.expire_at.is_some() {
+            flags |= FLAG_TTL;
+        }
+        buf[offset] = flags;
+        offset += 1;
+
+        offset += encode_varint(self.sequence_number, &mut buf[offset..]);
+        offset += encode_varint(self.offset, &mut buf[offset..]);
+        offset += encode_varint(self.val_len, &mut buf[offset..]);
+        offset += encode_varint(self.key.len() as u32, &mut buf[offset..]);
+        if let Some(ts) = self.expire_at {
+            offset += encode_varint(ts, &mut buf[offset..]);
+        }
+        offset
+    }
+
+    fn checksum(&self, meta: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(meta);
+        hasher.update(&self.key);
+        hasher.finalize()
+    }
+
+    fn encode_to<W: Write>(&self, writer: &mut W) -> Result<(), TitaniumError> {
+        let mut buf = [0u8; 64];
+        let len = self.encode_meta(&mut buf);
+
+        writer.write_all(&self.checksum(&buf[..len]).to_le_bytes())?;
+        writer.write_all(&buf[..len])?;
+        writer.write_all(&self.key)?;
+        Ok(())
+    }
+
+    fn decode_from<R: Read>(
+        reader: &mut R,
+        max_key_size: usize,
+    ) -> Result<Option<Self>, TitaniumError> {
+        let mut crc_buf = [0u8; 4];
+        match reader.read(&mut crc_buf[0..1]) {
+            Ok(0) => return Ok(None), // Clean EOF
+            Ok(1) => reader.read_exact(&mut crc_buf[1..])?,
+            Ok(_) => unreachable!(),
+            Err(e) => return Err(TitaniumError::Io(e)),
+        }
+        let crc = u32::from_le_bytes(crc_buf);
+        let flags = reader.read_u8()?;
+        let sequence_number: u64 = decode_varint(reader)?;
+        let offset: u64 = decode_varint(reader)?;
+        let val_len: u32 = decode_varint(reader)?;
+        let k_len: u32 = decode_varint(reader)?;
+        if k_len > max_key_size as u32 {
+            return Err(TitaniumError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Hint key too large",
+            )));
+        }
+        let expire_at = if flags & FLAG_TTL != 0 {
+            Some(decode_varint::<_, u64>(reader)?)
+        } else {
+            None
+        };
+
+        let mut key_buf = vec![0u8; k_len as usize];
+        reader.read_exact(&mut key_buf)?;
+
+        let entry = Self {
+            key: key_buf,
+            offset,
+            val_len,
+            sequence_number,
+            expire_at,
+            tombstone: flags & FLAG_TOMBSTONE != 0,
+        };
+
+        // 重新编码元数据以计算 CRC，保证与写入时的字节完全一致
+        let mut buf = [0u8; 64];
+        let len = entry.encode_meta(&mut buf);
+        if entry.checksum(&buf[..len]) != crc {
+            return Err(TitaniumError::CrcMismatch { expected: crc });
+        }
+        Ok(Some(entry))
+    }
+}
+
+/// Hint 文件写入器
+///
+/// 先写入 `NNNN.hbs.tmp`，`finish` 时 sync 并 rename，保证 Hint 文件要么完整，要么不存在。
+pub struct HintWriter {
+    writer: BufWriter<Box<dyn Storage>>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl HintWriter {
+    pub fn create(
+        fs: &dyn FileSystem,
+        data_path: &Path,
+        file_id: u32,
+        data_len: u64,
+    ) -> Result<Self, TitaniumError> {
+        let path = hint_path(data_path, file_id);
+        let tmp_path = path.with_extension("hbs.tmp");
+        let mut writer = BufWriter::new(fs.create_file(&tmp_path)?);
+        writer.write_all(HINT_MAGIC)?;
+        writer.write_all(&data_len.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            tmp_path,
+            path,
+        })
+    }
+
+    pub fn append(&mut self, entry: &HintEntry) -> Result<(), TitaniumError> {
+        entry.encode_to(&mut self.writer)
+    }
+
+    pub fn finish(self, fs: &dyn FileSystem) -> Result<(), TitaniumError> {
+        let mut file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync()?;
+        fs.rename(&self.tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// 扫描一个已封存的数据文件 (只读取 Header 和 Key)，为其生成 Hint 文件
+///
+/// 调用者需通过 `Decoder::set_version` 设置数据文件的格式版本。
+pub fn write_hint_from_data(
+    fs: &dyn FileSystem,
+    data_path: &Path,
+    file_id: u32,
+    reader: &dyn RandomAccessFile,
+    decoder: &mut Decoder,
+) -> Result<(), TitaniumError> {
+    let data_len = reader.len()?;
+    let mut hint = HintWriter::create(fs, data_path, file_id, data_len)?;
+    let mut reader = BufReader::new(FileAtReader {
+        reader,
+        offset: FileHeader::data_offset(decoder.version()),
+    });
+
+    // Hint 文件只记录已提交的条目，未完成的 WriteBatch 和提交标记都不会出现在其中
+    let mut replay = BatchReplay::new();
+    loop {
+        let offset = reader.stream_position()?;
+        let header = match decoder.decode_header_and_key(&mut reader)? {
+            Some(header) => header,
+            None => break,
+        };
+        let body_len = 4 + header.val_len as i64;
+        replay.feed(
+            header,
+            |h| HintEntry::from_header(h, offset),
+            |entry| hint.append(&entry),
+        )?;
+        reader.seek_relative(body_len)?;
+    }
+    replay.finish();
+
+    hint.finish(fs)
+}
+
+/// 读取数据文件对应的 Hint 文件，逐条回调
+///
+/// 返回 `Ok(false)` 表示 Hint 文件不存在或已失效 (魔数错误、长度不匹配、CRC 错误、截断)，
+/// 调用者应回退到全量扫描数据文件。回调可能已经处理了部分记录，因此回退扫描必须是幂等的。
+pub fn read_hint_file<F: FnMut(HintEntry)>(
+    fs: &dyn FileSystem,
+    data_path: &Path,
+    file_id: u32,
+    data_len: u64,
+    max_key_size: usize,
+    mut f: F,
+) -> Result<bool, TitaniumError> {
+    let path = hint_path(data_path, file_id);
+    if !fs.exists(&path) {
+        return Ok(false);
+    }
+
+    let file = fs.open_reader(&path)?;
+    let mut reader = BufReader::new(FileAtReader {
+        reader: file.as_ref(),
+        offset: 0,
+    });
+
+    let result = (|| -> Result<bool, TitaniumError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let recorded_len = reader.read_u64::<LittleEndian>()?;
+        if &magic != HINT_MAGIC || recorded_len != data_len {
+            return Ok(false);
+        }
+        while let Some(entry) = HintEntry::decode_from(&mut reader, max_key_size)? {
+            f(entry);
+        }
+        Ok(true)
+    })();
+
+    match result {
+        Ok(true) => Ok(true),
+        Ok(false) => {
+            eprintln!(
+                "Recover: Stale hint file for data file {}, falling back to full scan.",
+                file_id
+            );
+            Ok(false)
+        }
+        Err(e) => {
+            eprintln!(
+                "Recover: Invalid hint file for data file {} ({}), falling back to full scan.",
+                file_id, e
+            );
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemFileSystem;
+
+    fn sample_entries() -> Vec<HintEntry> {
+        vec![
+            HintEntry {
+                key: b"k1".to_vec(),
+                offset: 0,
+                val_len: 10,
+                sequence_number: 1,
+                expire_at: None,
+                tombstone: false,
+            },
+            HintEntry {
+                key: b"k2".to_vec(),
+                offset: 40,
+                val_len: 300,
+                sequence_number: 2,
+                expire_at: Some(1234567890),
+                tombstone: false,
+            },
+            HintEntry {
+                key: b"k1".to_vec(),
+                offset: 380,
+                val_len: 0,
+                sequence_number: 3,
+                expire_at: None,
+                tombstone: true,
+            },
+        ]
+    }
+
+    fn write_sample(fs: &MemFileSystem, dir: &Path, data_len: u64) {
+        let mut writer = HintWriter::create(fs, dir, 1, data_len).unwrap();
+        for entry in sample_entries() {
+            writer.append(&entry).unwrap();
+        }
+        writer.finish(fs).unwrap();
+    }
+
+    #[test]
+    fn test_hint_roundtrip() {
+        let fs = MemFileSystem::new();
+        let dir = Path::new("test_hint_roundtrip");
+        write_sample(&fs, dir, 400);
+        assert!(fs.exists(&hint_path(dir, 1)));
+
+        let mut loaded = Vec::new();
+        let valid = read_hint_file(&fs, dir, 1, 400, 1024, |e| loaded.push(e)).unwrap();
+        assert!(valid);
+        assert_eq!(loaded, sample_entries());
+    }
+
+    #[test]
+    fn test_hint_length_mismatch() {
+        let fs = MemFileSystem::new();
+        let dir = Path::new("test_hint_len");
+        write_sample(&fs, dir, 400);
+
+        let valid = read_hint_file(&fs, dir, 1, 500, 1024, |_| {}).unwrap();
+        assert!(!valid);
+    }
+
+    #[test]
+    fn test_hint_corrupted() {
+        let fs = MemFileSystem::new();
+        let dir = Path::new("test_hint_corrupt");
+        write_sample(&fs, dir, 400);
+
+        let mut file = fs.open_file(&hint_path(dir, 1)).unwrap();
+        file.seek(io::SeekFrom::End(-1)).unwrap();
+        file.write_all(b"X").unwrap();
+
+        let valid = read_hint_file(&fs, dir, 1, 400, 1024, |_| {}).unwrap();
+        assert!(!valid);
+    }
+}
@@ -1,12 +1,57 @@
 use hashbrown::HashTable;
+use std::collections::BTreeMap;
 use std::collections::hash_map::RandomState;
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::hash::BuildHasher;
+use std::ops::Bound;
+
+/// 有序索引的遍历结果：(Key, LogIndex)，按 Key 升序
+pub type IndexIter<'a> = Box<dyn Iterator<Item = (&'a [u8], LogIndex)> + 'a>;
 
 /// 索引器接口：负责管理 Key 到 LogIndex 的映射
 pub trait Indexer: Send + Sync {
-    fn put(&mut self, key: String, index: LogIndex);
-    fn get(&self, key: &str) -> Option<LogIndex>;
-    fn remove(&mut self, key: &str);
+    fn put(&mut self, key: Vec<u8>, index: LogIndex);
+    fn get(&self, key: &[u8]) -> Option<LogIndex>;
+    fn remove(&mut self, key: &[u8]);
+
+    /// 遍历所有索引项，顺序由具体实现决定
+    fn iter(&self) -> IndexIter<'_>;
+
+    /// 索引项数量
+    ///
+    /// 默认实现遍历全部索引项；内置索引直接返回容器的长度。
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 按 Key 升序遍历 `[lower, upper]` 范围内的索引
+    ///
+    /// 无序索引 (如 HashIndexer) 不支持范围遍历，返回 None。
+    fn range(&self, _lower: Bound<&[u8]>, _upper: Bound<&[u8]>) -> Option<IndexIter<'_>> {
+        None
+    }
+}
+
+/// 内置索引器类型，在打开 KVStore 时选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexerKind {
+    /// Arena + HashTable，内存占用小，点查快，不支持范围扫描
+    #[default]
+    Hash,
+    /// B-Tree，Key 有序，支持范围扫描和前缀扫描
+    BTree,
+}
+
+impl IndexerKind {
+    pub fn build(self) -> Box<dyn Indexer> {
+        match self {
+            IndexerKind::Hash => Box::new(HashIndexer::new()),
+            IndexerKind::BTree => Box::new(BTreeIndexer::new()),
+        }
+    }
 }
 
 /// 自定义的 Key Arena，用于紧凑存储 Key 的字节数据。
@@ -21,13 +66,12 @@ impl KeyArena {
     }
 
     /// 将 Key 写入 Arena，返回 (offset, len)
-    fn alloc(&mut self, key: &str) -> KeyRef {
+    fn alloc(&mut self, key: &[u8]) -> KeyRef {
         let offset = self.data.len() as u32;
-        let bytes = key.as_bytes();
-        self.data.extend_from_slice(bytes);
+        self.data.extend_from_slice(key);
         KeyRef {
             offset,
-            len: bytes.len() as u32,
+            len: key.len() as u32,
         }
     }
 
@@ -60,10 +104,8 @@ impl HashIndexer {
         }
     }
 
-    fn hash_key(&self, key: &str) -> u64 {
-        let mut hasher = self.hasher_builder.build_hasher();
-        key.as_bytes().hash(&mut hasher);
-        hasher.finish()
+    fn hash_key(&self, key: &[u8]) -> u64 {
+        self.hasher_builder.hash_one(key)
     }
 
     /// [Test Helper] 估算当前索引的内存占用 (Bytes)
@@ -75,13 +117,19 @@ impl HashIndexer {
     }
 }
 
+impl Default for HashIndexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Indexer for HashIndexer {
-    fn put(&mut self, key: String, index: LogIndex) {
+    fn put(&mut self, key: Vec<u8>, index: LogIndex) {
         let hash = self.hash_key(&key);
 
         if let Some((_, val)) = self
             .table
-            .find_mut(hash, |(kref, _)| self.arena.get(*kref) == key.as_bytes())
+            .find_mut(hash, |(kref, _)| self.arena.get(*kref) == key.as_slice())
         {
             *val = index;
             return;
@@ -92,28 +140,93 @@ impl Indexer for HashIndexer {
         self.table
             .insert_unique(hash, (key_ref, index), |(kref, _)| {
                 let bytes = self.arena.get(*kref);
-                let mut hasher = self.hasher_builder.build_hasher();
-                bytes.hash(&mut hasher);
-                hasher.finish()
+                self.hasher_builder.hash_one(bytes)
             });
     }
 
-    fn get(&self, key: &str) -> Option<LogIndex> {
+    fn get(&self, key: &[u8]) -> Option<LogIndex> {
         let hash = self.hash_key(key);
         self.table
-            .find(hash, |(kref, _)| self.arena.get(*kref) == key.as_bytes())
+            .find(hash, |(kref, _)| self.arena.get(*kref) == key)
             .map(|(_, val)| *val)
     }
 
-    fn remove(&mut self, key: &str) {
+    fn remove(&mut self, key: &[u8]) {
         let hash = self.hash_key(key);
         if let Ok(entry) = self
             .table
-            .find_entry(hash, |(kref, _)| self.arena.get(*kref) == key.as_bytes())
+            .find_entry(hash, |(kref, _)| self.arena.get(*kref) == key)
         {
             entry.remove();
         }
     }
+
+    fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    fn iter(&self) -> IndexIter<'_> {
+        Box::new(
+            self.table
+                .iter()
+                .map(|(kref, index)| (self.arena.get(*kref), *index)),
+        )
+    }
+}
+
+/// 有序索引：基于标准库 BTreeMap，按 Key 的字节序排列
+#[derive(Default)]
+pub struct BTreeIndexer {
+    map: BTreeMap<Vec<u8>, LogIndex>,
+}
+
+impl BTreeIndexer {
+    pub fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+}
+
+impl Indexer for BTreeIndexer {
+    fn put(&mut self, key: Vec<u8>, index: LogIndex) {
+        self.map.insert(key, index);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<LogIndex> {
+        self.map.get(key).copied()
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.map.remove(key);
+    }
+
+    fn iter(&self) -> IndexIter<'_> {
+        Box::new(self.map.iter().map(|(k, v)| (k.as_slice(), *v)))
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Option<IndexIter<'_>> {
+        // BTreeMap::range 在 lower > upper 或两端都是 Excluded 且相等时会 panic，此时返回空迭代器
+        let invalid = match (lower, upper) {
+            (Bound::Excluded(l), Bound::Excluded(u)) => l >= u,
+            (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) => {
+                l > u
+            }
+            _ => false,
+        };
+        if invalid {
+            return Some(Box::new(std::iter::empty()));
+        }
+        Some(Box::new(
+            self.map
+                .range::<[u8], _>((lower, upper))
+                .map(|(k, v)| (k.as_slice(), *v)),
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -151,10 +264,10 @@ mod tests {
     fn test_basic_put_get() {
         let mut indexer = HashIndexer::new();
         let idx = LogIndex::new(1, 100, 50);
-        indexer.put("key1".to_string(), idx);
+        indexer.put(b"key1".to_vec(), idx);
 
-        assert_eq!(indexer.get("key1"), Some(idx));
-        assert_eq!(indexer.get("key2"), None);
+        assert_eq!(indexer.get(b"key1"), Some(idx));
+        assert_eq!(indexer.get(b"key2"), None);
     }
 
     #[test]
@@ -163,36 +276,36 @@ mod tests {
         let idx1 = LogIndex::new(1, 100, 50);
         let idx2 = LogIndex::new(2, 200, 60);
 
-        indexer.put("key1".to_string(), idx1);
-        assert_eq!(indexer.get("key1"), Some(idx1));
+        indexer.put(b"key1".to_vec(), idx1);
+        assert_eq!(indexer.get(b"key1"), Some(idx1));
 
         // Update
-        indexer.put("key1".to_string(), idx2);
-        assert_eq!(indexer.get("key1"), Some(idx2));
+        indexer.put(b"key1".to_vec(), idx2);
+        assert_eq!(indexer.get(b"key1"), Some(idx2));
     }
 
     #[test]
     fn test_remove() {
         let mut indexer = HashIndexer::new();
         let idx = LogIndex::new(1, 100, 50);
-        indexer.put("key1".to_string(), idx);
+        indexer.put(b"key1".to_vec(), idx);
 
-        indexer.remove("key1");
-        assert_eq!(indexer.get("key1"), None);
+        indexer.remove(b"key1");
+        assert_eq!(indexer.get(b"key1"), None);
 
         // Remove non-existent
-        indexer.remove("key2"); // Should not panic
+        indexer.remove(b"key2"); // Should not panic
     }
 
     #[test]
     fn test_empty_key() {
         let mut indexer = HashIndexer::new();
         let idx = LogIndex::new(1, 0, 0);
-        indexer.put("".to_string(), idx);
-        assert_eq!(indexer.get(""), Some(idx));
+        indexer.put(b"".to_vec(), idx);
+        assert_eq!(indexer.get(b""), Some(idx));
 
-        indexer.remove("");
-        assert_eq!(indexer.get(""), None);
+        indexer.remove(b"");
+        assert_eq!(indexer.get(b""), None);
     }
 
     #[test]
@@ -201,14 +314,71 @@ mod tests {
         for i in 0..1000 {
             let key = format!("key-{}", i);
             let idx = LogIndex::new(1, i, 10);
-            indexer.put(key, idx);
+            indexer.put(key.into_bytes(), idx);
         }
 
         for i in 0..1000 {
             let key = format!("key-{}", i);
             let expected = LogIndex::new(1, i, 10);
-            assert_eq!(indexer.get(&key), Some(expected));
+            assert_eq!(indexer.get(key.as_bytes()), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        for mut indexer in [IndexerKind::Hash.build(), IndexerKind::BTree.build()] {
+            for i in 0..10u64 {
+                indexer.put(format!("key-{}", i).into_bytes(), LogIndex::new(1, i, 0));
+            }
+            indexer.remove(b"key-3");
+
+            let mut items: Vec<(Vec<u8>, u64)> = indexer
+                .iter()
+                .map(|(k, v)| (k.to_vec(), v.offset))
+                .collect();
+            items.sort();
+            assert_eq!(items.len(), 9);
+            assert!(
+                items
+                    .iter()
+                    .all(|(k, v)| *k == format!("key-{}", v).into_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_btree_range() {
+        let mut indexer = BTreeIndexer::new();
+        for (i, key) in [b"c", b"a", b"d", b"b"].iter().enumerate() {
+            indexer.put(key.to_vec(), LogIndex::new(1, i as u64, 0));
         }
+        indexer.remove(b"d");
+
+        let keys = |lower, upper| -> Vec<Vec<u8>> {
+            indexer
+                .range(lower, upper)
+                .unwrap()
+                .map(|(k, _)| k.to_vec())
+                .collect()
+        };
+        assert_eq!(
+            keys(Bound::Unbounded, Bound::Unbounded),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Excluded(b"a"), Bound::Included(b"c")),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        // 非法范围返回空，而不是 panic
+        assert!(keys(Bound::Included(b"c"), Bound::Excluded(b"a")).is_empty());
+        assert!(keys(Bound::Excluded(b"b"), Bound::Excluded(b"b")).is_empty());
+
+        // HashIndexer 不支持范围遍历
+        assert!(
+            HashIndexer::new()
+                .range(Bound::Unbounded, Bound::Unbounded)
+                .is_none()
+        );
     }
 
     #[test]
@@ -216,7 +386,7 @@ mod tests {
     fn benchmark_memory_and_performance() {
         // 1. 定义基准对照组 (Standard HashMap)
         struct StandardHashMapIndexer {
-            map: HashMap<String, LogIndex>,
+            map: HashMap<Vec<u8>, LogIndex>,
         }
 
         impl StandardHashMapIndexer {
@@ -228,21 +398,26 @@ mod tests {
         }
 
         impl Indexer for StandardHashMapIndexer {
-            fn put(&mut self, key: String, index: LogIndex) {
+            fn put(&mut self, key: Vec<u8>, index: LogIndex) {
                 self.map.insert(key, index);
             }
-            fn get(&self, key: &str) -> Option<LogIndex> {
+            fn get(&self, key: &[u8]) -> Option<LogIndex> {
                 self.map.get(key).copied()
             }
-            fn remove(&mut self, key: &str) {
+            fn remove(&mut self, key: &[u8]) {
                 self.map.remove(key);
             }
+            fn iter(&self) -> IndexIter<'_> {
+                Box::new(self.map.iter().map(|(k, v)| (k.as_slice(), *v)))
+            }
         }
 
         // 2. 准备测试数据
         let n = 1_000_000; // 100万 keys
         println!("Generating {} keys...", n);
-        let keys: Vec<String> = (0..n).map(|i| format!("key-{:010}", i)).collect();
+        let keys: Vec<Vec<u8>> = (0..n)
+            .map(|i| format!("key-{:010}", i).into_bytes())
+            .collect();
         let idx = LogIndex::new(1, 0, 0);
 
         // 3. 测试 HashIndexer (Arena + RawTable)
@@ -287,11 +462,11 @@ mod tests {
         let get_duration_std = start.elapsed();
         println!("Get time: {:?}", get_duration_std);
 
-        // 估算 HashMap 内存: Capacity * (SizeOf(String) + SizeOf(LogIndex)) + Heap(Strings)
-        // String(24B) + LogIndex(16B) = 40B per entry (stack)
+        // 估算 HashMap 内存: Capacity * (SizeOf(Vec<u8>) + SizeOf(LogIndex)) + Heap(Keys)
+        // Vec<u8>(24B) + LogIndex(16B) = 40B per entry (stack)
         let map_cap = std_indexer.map.capacity();
-        let struct_overhead = std::mem::size_of::<String>() + std::mem::size_of::<LogIndex>();
-        // 粗略估算：所有 Key 的堆内存占用 (String content)
+        let struct_overhead = std::mem::size_of::<Vec<u8>>() + std::mem::size_of::<LogIndex>();
+        // 粗略估算：所有 Key 的堆内存占用 (Key content)
         let heap_size: usize = keys.iter().map(|k| k.capacity()).sum();
         let mem_std = map_cap * struct_overhead + heap_size;
         println!("Approx Memory: {:.2} MB", mem_std as f64 / 1024.0 / 1024.0);
@@ -1,15 +1,28 @@
+use crate::batch::{BatchOp, BatchReplay, WriteBatch};
+use crate::compaction::{Compacter, MERGE_MANIFEST};
 use crate::config;
 use crate::error::TitaniumError;
-use crate::index::{HashIndexer, Indexer, LogIndex};
-use crate::log_entry::{Decoder, LogEntry};
-use crate::storage::{FileSystem, RandomAccessFile, Storage};
+use crate::hint;
+use crate::index::{IndexIter, Indexer, IndexerKind, LogIndex};
+use crate::log_entry::{
+    CURRENT_FORMAT_VERSION, Decoder, FILE_HEADER_SIZE, FILE_MAGIC, FileHeader,
+    LEGACY_FORMAT_VERSION, LogEntry, LogHeader,
+};
+use crate::shared::SharedKVStore;
+use crate::snapshot::{Snapshot, SnapshotList, VersionHistory};
+use crate::storage::{FileLock, FileSystem, LockMode, RandomAccessFile, Storage};
+use crate::syncer::{BackgroundSyncer, SyncFile};
+use crate::transaction::Transaction;
 use crate::writer::Writer;
+use parking_lot::{Mutex, RwLock};
 use std::collections::HashMap;
 use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::{Bound, Deref, RangeBounds};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// 一个辅助结构体，用于将 read_at 适配为 Read trait
 /// 这样 Decoder 就可以在不改变文件游标的情况下读取数据
@@ -63,34 +76,374 @@ impl<'a> Seek for FileAtReader<'a> {
     }
 }
 
+/// 一个只读的归档数据文件
+pub struct DataFile {
+    pub reader: Box<dyn RandomAccessFile>,
+    pub path: PathBuf,
+    /// 文件头中记录的格式版本，读取时据此选择解码逻辑
+    pub version: u8,
+}
+
+/// 存储引擎
+///
+/// 由两部分组成：读路径使用的 [`KeyDir`] (索引、数据文件句柄、快照)，以及写路径独占的活跃文件。
+/// 读接口通过 `Deref` 直接来自 KeyDir；写入经由 [`WriteHandle`] 追加到活跃文件后再发布到 KeyDir。
+/// `SharedKVStore` 把两部分放在不同的锁中，读者不会被写入和合并阻塞。
 pub struct KVStore {
+    keydir: KeyDir,
+    // 活跃文件，只读模式下为 None；排在目录锁之前，drop 时先落盘再释放锁
+    active: Option<ActiveFile>,
+    // 数据目录的 LOCK 文件锁，随 KVStore 一起释放 (只读挂载上无法创建锁文件时为 None)
+    lock: Option<Box<dyn FileLock>>,
+}
+
+/// 读路径需要的全部状态：索引、数据文件句柄、快照和版本历史
+///
+/// 写入先追加到活跃文件，再在 KeyDir 上发布 (更新索引和序列号)；读取只访问这部分，
+/// 条目通过 `RandomAccessFile::read_at` 读取，不依赖文件游标。
+pub struct KeyDir {
     pub(crate) indexer: Box<dyn Indexer>,
-    writer: Writer<Box<dyn Storage>>,
     pub(crate) fs: Arc<dyn FileSystem>,
-    pub(crate) file_map: HashMap<u32, (Box<dyn RandomAccessFile>, PathBuf)>,
-    data_path: PathBuf,
-    active_file_id: u32,
+    pub(crate) file_map: HashMap<u32, DataFile>,
+    pub(crate) data_path: PathBuf,
     pub(crate) config: config::ConfigWatcher,
+    // 活跃文件的 ID 和只读句柄，只读模式下为 None
+    active_file: Option<ActiveReader>,
+    // 已发布到索引的最大序列号
     current_seq_no: u64,
+    // 自上次合并以来新增的归档文件数，用于触发合并和写入背压
+    pending_files: usize,
+    // 活跃快照，以及快照仍可能需要的旧版本
+    pub(crate) snapshots: SnapshotList,
+    pub(crate) history: VersionHistory,
+}
+
+/// 活跃文件的 ID 和读路径使用的只读句柄
+type ActiveReader = (u32, Box<dyn RandomAccessFile>);
+
+/// 写路径独占的状态：活跃文件的 Writer 和序列号分配
+///
+/// 所有写入经由它串行追加；`SharedKVStore` 把它放在独立的互斥锁中。
+pub(crate) struct ActiveFile {
+    writer: Writer<Box<dyn Storage>>,
+    id: u32,
+    fs: Arc<dyn FileSystem>,
+    data_path: PathBuf,
+    config: config::ConfigWatcher,
+    // 已分配的最大序列号
+    seq_no: u64,
+    last_compaction: Option<Instant>,
+    // 自上次磁盘空间检查以来写入的字节数
+    bytes_since_space_check: u64,
+    // 活跃文件的独立同步句柄，组提交或后台同步需要时才打开
+    sync_file: Option<SyncFile>,
+    // 组提交：是否开启，以及最后一个等待 fsync 的序列号
+    group_commit: bool,
+    pending_sync: u64,
+    // WriteMod::Interval 的后台同步线程，首次以该模式写入时启动
+    syncer: Option<BackgroundSyncer>,
+    // 已经 close，drop 时不再重复落盘
+    closed: bool,
+}
+
+/// 处于 stall 状态时，每次写入前的基础延迟；每多一个待合并文件再增加一个单位
+const WRITE_STALL_DELAY: Duration = Duration::from_millis(1);
+
+/// KVStore 运行状态统计，见 `KVStore::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// 索引中的 Key 数量；已过期但尚未被合并清理的 Key 也计算在内
+    pub keys: usize,
+    /// 数据文件数 (包括活跃文件)
+    pub data_files: usize,
+    /// 所有数据文件的总大小 (字节)
+    pub disk_usage: u64,
+    /// 自上次合并以来新增的归档文件数
+    pub pending_compaction_files: usize,
+    /// 最后一次写入的序列号
+    pub sequence_number: u64,
+    pub read_only: bool,
+}
+
+impl Stats {
+    /// 编码为单行 JSON 对象
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"keys\":{},\"data_files\":{},\"disk_usage\":{},\"pending_compaction_files\":{},\"sequence_number\":{},\"read_only\":{}}}",
+            self.keys,
+            self.data_files,
+            self.disk_usage,
+            self.pending_compaction_files,
+            self.sequence_number,
+            self.read_only
+        )
+    }
+}
+
+/// TTL 的上限 (约 2.9 亿年)，保证当前时间加上 TTL 不会溢出；超过上限的写入返回 `InvalidInput`
+pub const MAX_TTL: Duration = Duration::from_millis(i64::MAX as u64);
+
+/// 数据目录锁文件名
+pub const LOCK_FILE: &str = "LOCK";
+
+/// 每写入这么多字节检查一次磁盘可用空间 (轮转前总会检查)
+const DISK_CHECK_INTERVAL_BYTES: u64 = 4 * 1024 * 1024;
+
+/// KVStore 构建器
+///
+/// 默认使用配置中 `indexer` 指定的内置索引；也可以通过 `indexer` 传入任意 `Indexer` 实现
+/// (例如带统计的索引，或针对特定 Key 分布优化的索引)。
+pub struct KVStoreBuilder {
+    config: config::ConfigWatcher,
+    fs: Arc<dyn FileSystem>,
+    indexer: Option<Box<dyn Indexer>>,
+    read_only: bool,
+}
+
+impl KVStoreBuilder {
+    /// 使用内置索引类型，覆盖配置中的 `indexer`
+    pub fn indexer_kind(mut self, kind: IndexerKind) -> Self {
+        self.indexer = Some(kind.build());
+        self
+    }
+
+    /// 使用自定义索引器，传入时必须为空，索引由 `restore` 填充
+    pub fn indexer(mut self, indexer: Box<dyn Indexer>) -> Self {
+        self.indexer = Some(indexer);
+        self
+    }
+
+    /// 以只读模式打开
+    ///
+    /// 不创建目录和活跃文件，不处理合并遗留文件，恢复时也不截断损坏的尾部，因此可用于只读挂载；
+    /// 持有共享目录锁，可与其他只读实例共存，但与读写实例互斥。所有写操作返回 `ReadOnly`。
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn open(self) -> Result<KVStore, TitaniumError> {
+        let indexer = match self.indexer {
+            Some(indexer) => indexer,
+            None => self.config.get().indexer.build(),
+        };
+        KVStore::open(self.config, self.fs, indexer, self.read_only)
+    }
 }
 
 impl KVStore {
+    /// 转换为可在线程间共享的句柄，见 `SharedKVStore`
+    pub fn into_shared(self) -> Result<SharedKVStore, TitaniumError> {
+        SharedKVStore::new(self)
+    }
+
+    /// 使用配置中的索引类型 (`indexer`) 打开 KVStore
     pub fn new(
         config: config::ConfigWatcher,
         fs: Arc<dyn FileSystem>,
+    ) -> Result<Self, TitaniumError> {
+        Self::builder(config, fs).open()
+    }
+
+    /// 创建 KVStore 构建器，用于在打开时替换索引器等选项
+    pub fn builder(config: config::ConfigWatcher, fs: Arc<dyn FileSystem>) -> KVStoreBuilder {
+        KVStoreBuilder {
+            config,
+            fs,
+            indexer: None,
+            read_only: false,
+        }
+    }
+
+    fn open(
+        config: config::ConfigWatcher,
+        fs: Arc<dyn FileSystem>,
+        indexer: Box<dyn Indexer>,
+        read_only: bool,
     ) -> Result<Self, TitaniumError> {
         // 扫描目录，查找数据文件，如果没有目录，则创建对应目录，并初始化bs文件
         let data_path = config.get().data_dir;
         let root_path = Path::new(&data_path);
-        if !fs.exists(root_path) {
-            fs.create_dir_all(root_path)?;
-        }
+        let lock = if read_only {
+            Self::lock_dir_read_only(fs.as_ref(), root_path)?
+        } else {
+            if !fs.exists(root_path) {
+                fs.create_dir_all(root_path)?;
+            }
+            // 在读写任何文件之前锁定目录，防止多个实例交错追加同一个活跃文件
+            let lock = Self::lock_dir(fs.as_ref(), root_path, LockMode::Exclusive)?;
+
+            // 0. 处理上一次合并遗留的临时文件或未完成的替换
+            Compacter::recover(fs.as_ref(), root_path)?;
+            Some(lock)
+        };
 
         // 1. 扫描所有 .bs 文件并提取 ID
+        let file_ids = Self::list_data_file_ids(fs.as_ref(), root_path)?;
+
+        // 2. 打开所有数据文件 (只读) 并校验文件头，跳过不属于 Titanium 的外部文件
+        let (max_key, max_val) = config.max_sizes();
+        let mut decoder = Decoder::new(max_key, max_val);
+        let mut file_map = HashMap::new();
+        for &id in &file_ids {
+            let path = root_path.join(format!("{:04}.bs", id));
+            let reader = fs.open_reader(&path)?;
+            match Self::probe_version(reader.as_ref(), &mut decoder)? {
+                Some(version) => {
+                    file_map.insert(
+                        id,
+                        DataFile {
+                            reader,
+                            path,
+                            version,
+                        },
+                    );
+                }
+                None => eprintln!(
+                    "Open: Skipping foreign file {:?}: not a Titanium data file.",
+                    path
+                ),
+            }
+        }
+
+        // 3. 确定 active_file_id
+        // 只有编号最大的文件是当前版本且没写满时才复用（追加），否则轮转到新文件。
+        // 新 ID 总是大于目录中所有文件 (包括被跳过的外部文件)，避免轮转时覆盖它们。
+        let last_id = file_ids.last().copied().unwrap_or(0);
+        let reuse_last = !read_only
+            && file_map.get(&last_id).is_some_and(|f| {
+                f.version == CURRENT_FORMAT_VERSION
+                    && f.reader
+                        .len()
+                        .is_ok_and(|len| len < config.max_file_size() as u64)
+            });
+        let active_file_id = if reuse_last { last_id } else { last_id + 1 };
+
+        // 4. 打开活跃文件 (Append 模式)，只读模式下没有活跃文件
+        // 对于 MemFileSystem，create_file 会截断，open_file 会保留。
+        // 因此复用时使用 open_file，新文件使用 create_file 并写入文件头。
+        let writer = if read_only {
+            None
+        } else if reuse_last {
+            // 从归档列表中移除，因为它将作为 active file
+            let DataFile { path, .. } = file_map.remove(&last_id).unwrap();
+            let mut active_file = fs.open_file(&path)?;
+            let file_len = active_file.len()?;
+            if file_len < FILE_HEADER_SIZE {
+                // 文件头不完整 (如创建后立即崩溃)，不可能包含有效条目，重新初始化
+                active_file.set_len(0)?;
+                active_file.seek(io::SeekFrom::Start(0))?;
+                Some(Writer::create(active_file)?)
+            } else {
+                // 打开现有文件进行追加写时，必须将游标移动到文件末尾
+                active_file.seek(io::SeekFrom::Start(file_len))?;
+                Some(Writer::new(active_file, file_len))
+            }
+        } else {
+            let active_path = root_path.join(format!("{:04}.bs", active_file_id));
+            Some(Writer::create(fs.create_file(&active_path)?)?)
+        };
+
+        // 只有上次合并之后封存的归档文件需要合并；从未合并过时全部视为待合并
+        let merged = Compacter::read_progress(fs.as_ref(), root_path);
+        let pending_files = file_map
+            .keys()
+            .filter(|&&id| merged.is_none_or(|last| id > last))
+            .count();
+
+        // 读路径通过单独的只读句柄读取活跃文件，不与 Writer 共享游标
+        let (active, active_file) = match writer {
+            Some(writer) => {
+                let path = root_path.join(format!("{:04}.bs", active_file_id));
+                let reader = fs.open_reader(&path)?;
+                let active = ActiveFile::new(
+                    writer,
+                    active_file_id,
+                    fs.clone(),
+                    root_path.to_path_buf(),
+                    config.clone(),
+                );
+                (Some(active), Some((active_file_id, reader)))
+            }
+            None => (None, None),
+        };
+
+        Ok(KVStore {
+            keydir: KeyDir {
+                indexer,
+                fs,
+                file_map,
+                data_path: root_path.to_path_buf(),
+                config,
+                active_file,
+                current_seq_no: 0,
+                pending_files,
+                snapshots: SnapshotList::default(),
+                history: VersionHistory::default(),
+            },
+            active,
+            lock,
+        })
+    }
+
+    /// 锁定数据目录 (LOCK 文件)，与其他进程或 KVStore 持有的锁冲突时返回 `DirectoryLocked`
+    pub(crate) fn lock_dir(
+        fs: &dyn FileSystem,
+        root_path: &Path,
+        mode: LockMode,
+    ) -> Result<Box<dyn FileLock>, TitaniumError> {
+        fs.lock_file(&root_path.join(LOCK_FILE), mode)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::WouldBlock => {
+                    TitaniumError::DirectoryLocked(root_path.to_path_buf())
+                }
+                _ => TitaniumError::Io(e),
+            })
+    }
+
+    /// 只读打开时的准备：拒绝有未完成合并的目录，并加共享锁
+    ///
+    /// 只读挂载上没有 LOCK 文件时无法创建它，此时不可能有读写实例，跳过加锁。
+    fn lock_dir_read_only(
+        fs: &dyn FileSystem,
+        root_path: &Path,
+    ) -> Result<Option<Box<dyn FileLock>>, TitaniumError> {
+        // 合并替换进行到一半时新旧文件混杂，只有读写打开才能完成恢复
+        if fs.exists(&root_path.join(MERGE_MANIFEST)) {
+            return Err(TitaniumError::Unsupported(
+                "unfinished compaction must be recovered by a read-write open first",
+            ));
+        }
+        match Self::lock_dir(fs, root_path, LockMode::Shared) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(TitaniumError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::ReadOnlyFilesystem | io::ErrorKind::PermissionDenied
+                ) =>
+            {
+                eprintln!(
+                    "Open: Cannot create lock file ({}), opening without a lock.",
+                    e
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 扫描目录中所有 NNNN.bs 数据文件，返回升序排列的 ID
+    pub(crate) fn list_data_file_ids(
+        fs: &dyn FileSystem,
+        root_path: &Path,
+    ) -> Result<Vec<u32>, TitaniumError> {
         let mut file_ids: Vec<u32> = fs
             .list_files(root_path)?
             .into_iter()
-            .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "bs"))
+            .filter(|path| {
+                fs.metadata(path).is_ok_and(|m| m.is_file)
+                    && path.extension().is_some_and(|ext| ext == "bs")
+            })
             .filter_map(|path| {
                 path.file_stem()
                     .and_then(|s| s.to_str())
@@ -99,105 +452,71 @@ impl KVStore {
             .collect();
 
         file_ids.sort();
+        Ok(file_ids)
+    }
 
-        // 2. 确定 active_file_id
-        // 检查最后一个文件是否写满，没写满则追加（复用），写满则轮转。
-        let mut active_file_id = 1;
-
-        if let Some(&last_id) = file_ids.last() {
-            let last_path = root_path.join(format!("{:04}.bs", last_id));
-            let len = fs.metadata(&last_path).map(|m| m.len).unwrap_or(0);
+    /// 识别数据文件的格式版本
+    ///
+    /// - 以魔数开头：返回文件头中的版本；版本比当前程序新时报错，拒绝用旧程序打开新数据目录。
+    /// - 没有魔数：尝试按无文件头的旧格式解码第一个条目，成功则为 `LEGACY_FORMAT_VERSION`。
+    /// - 都不是：返回 None，表示外部文件。
+    ///
+    /// 长度不足一个文件头、且内容是魔数前缀的文件 (创建后立即崩溃) 视为当前版本的空文件。
+    pub(crate) fn probe_version(
+        reader: &dyn RandomAccessFile,
+        decoder: &mut Decoder,
+    ) -> Result<Option<u8>, TitaniumError> {
+        let len = reader.len()?;
+        if len < FILE_HEADER_SIZE {
+            let mut buf = vec![0u8; len as usize];
+            FileAtReader { reader, offset: 0 }.read_exact(&mut buf)?;
+            return Ok(FILE_MAGIC
+                .starts_with(&buf)
+                .then_some(CURRENT_FORMAT_VERSION));
+        }
 
-            if len < config.max_file_size() as u64 {
-                active_file_id = last_id;
-                file_ids.pop(); // 从归档列表中移除，因为它将作为 active file
-            } else {
-                active_file_id = last_id + 1;
+        let mut buf = [0u8; FILE_HEADER_SIZE as usize];
+        FileAtReader { reader, offset: 0 }.read_exact(&mut buf)?;
+        if let Some(header) = FileHeader::decode(&buf) {
+            if !FileHeader::is_supported(header.version) {
+                return Err(TitaniumError::UnsupportedVersion(header.version));
             }
+            return Ok(Some(header.version));
         }
 
-        let active_file_name = format!("{:04}.bs", active_file_id);
-        let active_path = root_path.join(active_file_name);
-
-        // 3. 打开活跃文件 (Append 模式)
-        // FileSystem 抽象通常不区分 append/create 选项，而是由实现决定
-        // 这里我们假设 create_file 或 open_file 能满足需求。
-        // 对于 MemFileSystem，create_file 会截断，open_file 会保留。
-        // 我们需要一个能 "Open or Create" 的语义。
-        // 简化起见，如果存在则 open，不存在则 create。
-        let mut active_file = if fs.exists(&active_path) {
-            fs.open_file(&active_path)?
-        } else {
-            fs.create_file(&active_path)?
-        };
-
-        // TODO: [File Header] If active_file is new (len == 0), write the File Header immediately.
-        // If it's an existing file, read and validate the Magic Number and Version.
-
-        let file_len = active_file.len()?;
-
-        // 打开现有文件进行追加写时，必须将游标移动到文件末尾
-        active_file.seek(io::SeekFrom::Start(file_len))?;
-
-        let writer = Writer::new(active_file, file_len);
-
-        // 4. 加载旧文件到 file_map (只读)
-        let mut file_map = HashMap::new();
-        for &id in &file_ids {
-            let path = root_path.join(format!("{:04}.bs", id));
-            let file = fs.open_reader(&path)?;
-            // TODO: [File Header] Validate Magic Number and Version for each immutable file.
-            // 注意：open_reader 返回 Box<dyn RandomAccessFile>
-            file_map.insert(id, (file, path));
+        decoder.set_version(LEGACY_FORMAT_VERSION);
+        match decoder.decode_header_and_key(&mut FileAtReader { reader, offset: 0 }) {
+            Ok(Some(_)) => Ok(Some(LEGACY_FORMAT_VERSION)),
+            _ => Ok(None),
         }
-
-        Ok(KVStore {
-            indexer: Box::new(HashIndexer::new()),
-            writer,
-            fs,
-            file_map: file_map,
-            data_path: root_path.to_path_buf(),
-            active_file_id,
-            config,
-            current_seq_no: 0,
-        })
     }
 
-    /// 获取下一个序列号，如果溢出则返回错误
-    fn next_seq_no(&mut self) -> Result<u64, TitaniumError> {
-        self.current_seq_no = self.current_seq_no.checked_add(1).ok_or_else(|| {
-            TitaniumError::Io(io::Error::new(
-                io::ErrorKind::Other,
-                "Sequence number overflow: database limit reached",
-            ))
-        })?;
-        Ok(self.current_seq_no)
+    /// 拆分为读路径、写路径和目录锁，供 `SharedKVStore` 分别加锁
+    pub(crate) fn into_parts(self) -> (KeyDir, Option<ActiveFile>, Option<Box<dyn FileLock>>) {
+        (self.keydir, self.active, self.lock)
     }
 
-    pub fn set(&mut self, key: String, value: Vec<u8>) -> Result<(), TitaniumError> {
-        // 0. 检查是否需要轮转文件
-        if self.writer.current_offset() >= self.config.max_file_size() as u64 {
-            self.rotate()?;
+    /// 写操作的入口：先执行写入背压的休眠，见 `KeyDir::stall_delay`
+    fn write_handle(&mut self) -> Result<WriteHandle<'_>, TitaniumError> {
+        if let Some(delay) = self.keydir.stall_delay() {
+            thread::sleep(delay);
         }
+        self.handle()
+    }
 
-        // TODO: Support WriteBatch (Atomic updates for multiple keys).
-        // This would involve writing a batch header or using a transaction marker in EntryType.
-        let seq_no = self.next_seq_no()?;
-
-        // 1. write to log file
-        let entry = LogEntry::new(key, value, seq_no).build();
-        let offset = self.writer.write_entry(&entry)?;
-        // use config to decide when to sync
-        match self.config.write_mod() {
-            config::WriteMod::Sync => self.writer.sync()?,
-            config::WriteMod::Buffer => self.writer.flush_to_os()?,
+    /// 只读模式下在产生任何副作用 (背压合并、读取旧值等) 之前返回 `ReadOnly`
+    fn handle(&mut self) -> Result<WriteHandle<'_>, TitaniumError> {
+        match &mut self.active {
+            Some(active) => Ok(WriteHandle {
+                active,
+                keydir: KeyDirRef::Owned(&mut self.keydir),
+            }),
+            None => Err(TitaniumError::ReadOnly),
         }
-        // 2. update indexer
-        self.indexer.put(
-            entry.key,
-            LogIndex::new(self.active_file_id, offset, entry.value.len() as u32),
-        );
-        Ok(())
+    }
+
+    pub fn set(&mut self, key: impl Into<Vec<u8>>, value: Vec<u8>) -> Result<(), TitaniumError> {
+        self.write_handle()?.set(key, value)
     }
 
     /// 支持 TTL (过期时间) 的写入接口
@@ -206,177 +525,177 @@ impl KVStore {
     /// - `ttl`: 数据存活时长。例如 `Duration::from_secs(60)` 表示 60 秒后过期。
     pub fn set_with_ttl(
         &mut self,
-        key: &str,
+        key: impl Into<Vec<u8>>,
         value: Vec<u8>,
         ttl: std::time::Duration,
     ) -> Result<(), TitaniumError> {
-        if self.writer.current_offset() >= self.config.max_file_size() as u64 {
-            self.rotate()?;
-        }
-
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        let expire_at = now + ttl.as_millis() as u64;
-
-        let seq_no = self.next_seq_no()?;
-
-        let entry = LogEntry::new(key.to_string(), value, seq_no)
-            .with_ttl(expire_at)
-            .build();
-        let offset = self.writer.write_entry(&entry)?;
-        match self.config.write_mod() {
-            config::WriteMod::Sync => self.writer.sync()?,
-            config::WriteMod::Buffer => self.writer.flush_to_os()?,
-        }
-        self.indexer.put(
-            entry.key,
-            LogIndex::new(self.active_file_id, offset, entry.value.len() as u32),
-        );
-        Ok(())
+        self.write_handle()?.set_with_ttl(key, value, ttl)
     }
 
-    pub fn remove(&mut self, key: &str) -> Result<(), TitaniumError> {
-        // 1. 如果 Key 存在，则写入 Tombstone
-        if self.indexer.get(&key).is_some() {
-            let seq_no = self.next_seq_no()?;
-
-            let entry = LogEntry::new_tombstone(key.to_string(), seq_no);
-            self.writer.write_entry(&entry)?;
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Result<(), TitaniumError> {
+        self.write_handle()?.remove(key)
+    }
 
-            match self.config.write_mod() {
-                config::WriteMod::Sync => self.writer.sync()?,
-                config::WriteMod::Buffer => self.writer.flush_to_os()?,
-            }
-            // 2. 从内存索引中移除
-            self.indexer.remove(&key);
-        }
-        Ok(())
+    /// 比较并交换：当前值等于 `expected` 时写入 `new`，返回是否写入
+    ///
+    /// `expected` 为 None 表示要求 Key 不存在 (已过期视为不存在)。
+    pub fn compare_and_swap(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write_handle()?.compare_and_swap(key, expected, new)
     }
 
-    /// 轮转活跃文件：将当前文件转为只读归档，并创建新的活跃文件
-    fn rotate(&mut self) -> Result<(), TitaniumError> {
-        // 1. 强制刷盘，确保旧数据落盘
-        self.writer.sync()?;
+    /// 仅当 Key 不存在 (或已过期) 时写入，返回是否写入 (NX)
+    pub fn set_if_absent(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write_handle()?.set_if_absent(key, value)
+    }
 
-        // 2. 将当前的 active_file 加入到 file_map 中 (作为只读)
-        // 注意：我们需要重新以只读模式打开它，或者复用路径
-        let old_id = self.active_file_id;
-        let old_path = self.data_path.join(format!("{:04}.bs", old_id));
+    /// 仅当 Key 存在且未过期时写入，返回是否写入 (XX)
+    pub fn set_if_present(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write_handle()?.set_if_present(key, value)
+    }
 
-        // 重新打开为只读句柄放入 map，供 get 使用
-        let old_file = self.fs.open_reader(&old_path)?;
-        self.file_map.insert(old_id, (old_file, old_path));
+    /// 版本条件写入：仅当 Key 当前版本的 `sequence_number` 等于 `expected` 时写入，返回是否写入
+    ///
+    /// 版本号来自 `get` 返回的 `LogEntry::sequence_number`，Key 不存在或已过期时不会写入。
+    pub fn set_if_version(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        expected: u64,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write_handle()?.set_if_version(key, expected, value)
+    }
 
-        // 3. 更新 active_file_id 并创建新文件
-        self.active_file_id += 1;
-        let new_path = self
-            .data_path
-            .join(format!("{:04}.bs", self.active_file_id));
+    /// 原子地写入一个 WriteBatch
+    ///
+    /// 批次中的条目与提交标记连续写入同一个数据文件，全部写入后才更新索引。
+    /// 崩溃时若提交标记没有落盘，恢复时整个批次都会被丢弃。
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<(), TitaniumError> {
+        self.write_handle()?.write_batch(batch)
+    }
 
-        let new_file = self.fs.create_file(&new_path)?;
+    /// 提交事务：校验读集合后把事务中的写入作为一个 WriteBatch 原子地写入
+    ///
+    /// 读过的 Key 在事务开始后被修改时返回 `TransactionConflict`，事务由其他 KVStore 创建时返回
+    /// `TransactionStoreMismatch`，两种情况下事务中的写入都会被丢弃。
+    pub fn commit(&mut self, txn: Transaction) -> Result<(), TitaniumError> {
+        self.write_handle()?.commit(txn)
+    }
 
-        // 4. 替换 Writer
-        // Writer::new 会初始化 offset，如果是新文件则为 0
-        self.writer = Writer::new(new_file, 0);
+    /// 手动触发合并：重写所有归档文件，只保留仍然有效的条目
+    pub fn compact(&mut self) -> Result<(), TitaniumError> {
+        self.handle()?.compact()
+    }
 
-        Ok(())
+    /// 关闭 KVStore：刷新缓冲区并 fsync 活跃文件，停止后台同步线程，释放目录锁
+    ///
+    /// drop 时会尽力执行同样的步骤，但只能记录错误；需要确认数据已经落盘时应显式调用 `close`。
+    pub fn close(self) -> Result<(), TitaniumError> {
+        let (_, active, lock) = self.into_parts();
+        let result = active.map_or(Ok(()), ActiveFile::close);
+        // 数据落盘后再释放目录锁，其他实例打开时能看到完整的数据
+        drop(lock);
+        result
     }
 
     /// 手动触发刷盘，将缓冲区数据写入磁盘
+    ///
+    /// 只读模式下没有需要落盘的数据，直接返回。
     pub fn sync(&mut self) -> Result<(), TitaniumError> {
-        self.writer.sync()
+        match &mut self.active {
+            Some(active) => active.sync(),
+            None => Ok(()),
+        }
     }
 
-    pub fn get(&self, key: String) -> Result<Option<LogEntry>, TitaniumError> {
-        let log_index = match self.indexer.get(&key) {
-            Some(index) => index,
-            None => return Ok(None),
-        };
-
-        // 区分读取的是归档文件还是当前的活跃文件
-        // 如果是活跃文件，我们需要从 writer 中获取（或者如果 writer 的文件句柄支持 read，也可以直接用）
-        // 但为了简化，我们在 new/rotate 时确保 active_file 也是可读的，
-        // 并且我们不把 active_file 放入 file_map，所以这里需要特殊处理
-        let reader: &dyn RandomAccessFile = if log_index.file_id == self.active_file_id {
-            self.writer.get_ref().as_ref()
-        } else {
-            self.file_map[&log_index.file_id].0.as_ref()
-        };
-
-        // 使用 FileAtReader 替代 seek，实现无锁并发读取
-        let mut reader = FileAtReader {
-            reader,
-            offset: log_index.offset,
-        };
-
-        // 使用 Thread-local Decoder 减少高频读取时的内存分配开销
-        thread_local! {
-            static DECODER: std::cell::RefCell<Decoder> = std::cell::RefCell::new(Decoder::new(0, 0));
+    /// 恢复时截断文件中 offset 之后损坏的尾部
+    ///
+    /// 只读模式下不修改文件，只忽略 offset 之后的内容。
+    fn truncate_tail(
+        &mut self,
+        file_id: u32,
+        offset: u64,
+        reason: &str,
+    ) -> Result<(), TitaniumError> {
+        if self.keydir.is_read_only() {
+            eprintln!(
+                "Recover: {} at file {} offset {}. Ignoring the rest of the file (read-only).",
+                reason, file_id, offset
+            );
+            return Ok(());
         }
-
-        let entry = DECODER.with(|cell| {
-            let mut decoder = cell.borrow_mut();
-            let (max_key, max_val) = self.config.max_sizes();
-            decoder.set_limits(max_key, max_val);
-            decoder.decode_from(&mut reader)?.ok_or_else(|| {
-                TitaniumError::Io(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Unexpected EOF at indexed offset",
-                ))
-            })
-        })?;
-
-        // [TTL Check] 检查数据是否过期
-        if let Some(expire_at) = entry.expire_at() {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
-            if now > expire_at {
-                // 数据已过期，返回 None (惰性删除：索引中还在，但用户读不到)
-                return Ok(None);
+        eprintln!(
+            "Recover: {} at file {} offset {}. Truncating.",
+            reason, file_id, offset
+        );
+        match &mut self.active {
+            Some(active) if active.id == file_id => {
+                // 关键修复：如果复用了 active file 且发生了截断，必须同时更新 writer 的 offset 和文件游标
+                active.writer.truncate(offset)?;
+            }
+            _ => {
+                // 对于只读的归档文件，需要重新以写模式打开才能截断
+                let write_file = self
+                    .keydir
+                    .fs
+                    .open_file(&self.keydir.file_map[&file_id].path)?;
+                write_file.set_len(offset)?;
             }
         }
-
-        Ok(Some(entry))
+        Ok(())
     }
 
     // 程序重启后，恢复 KVStore 状态
     pub fn restore(&mut self) -> Result<(), TitaniumError> {
-        let (max_key, max_val) = self.config.max_sizes();
+        let (max_key, max_val) = self.keydir.config.max_sizes();
         let mut decoder = Decoder::new(max_key, max_val);
 
-        // TODO: Implement Hint file loading for faster startup.
-        // 1. Check if a valid .hbs file exists for each .bs file.
-        // 2. If yes, load index directly from .hbs (avoids reading large values).
-        // 3. If no (or checksum mismatch), fall back to the current full scan recovery.
         // 1. 获取所有 file_id 并排序，确保按时间顺序恢复数据 (旧 -> 新)
-        let mut file_ids: Vec<u32> = self.file_map.keys().cloned().collect();
+        let mut file_ids: Vec<u32> = self.keydir.file_map.keys().cloned().collect();
         file_ids.sort();
 
-        // 别忘了加上当前的 active_file_id，因为它不在 file_map 中
-        file_ids.push(self.active_file_id);
+        // 别忘了加上当前的 active_file_id，因为它不在 file_map 中 (只读模式下没有活跃文件)
+        let active_id = self.keydir.active_file.as_ref().map(|(id, _)| *id);
+        file_ids.extend(active_id);
 
         for file_id in &file_ids {
-            let is_active = *file_id == self.active_file_id;
+            let is_active = Some(*file_id) == active_id;
 
-            let active_path_buf; // 声明变量以延长生命周期
-            let (reader, file_path): (&dyn RandomAccessFile, &PathBuf) = if is_active {
-                active_path_buf = self.data_path.join(format!("{:04}.bs", file_id));
-                (self.writer.get_ref().as_ref(), &active_path_buf)
-            } else {
-                let (f, p) = &self.file_map[file_id];
-                (f.as_ref(), p)
+            // 归档文件优先从 Hint 文件加载索引 (不读取数据文件)，Hint 缺失或失效时回退到全量扫描
+            if !is_active && self.keydir.load_hint(*file_id)? {
+                continue;
+            }
+
+            let (reader, version): (&dyn RandomAccessFile, u8) = match &self.keydir.active_file {
+                Some((_, reader)) if is_active => (reader.as_ref(), CURRENT_FORMAT_VERSION),
+                _ => {
+                    let f = &self.keydir.file_map[file_id];
+                    (f.reader.as_ref(), f.version)
+                }
             };
 
-            let mut reader = std::io::BufReader::new(FileAtReader { reader, offset: 0 });
+            // 跳过固定长度的文件头，按文件的格式版本解码条目
+            decoder.set_version(version);
+            let mut reader = std::io::BufReader::new(FileAtReader {
+                reader,
+                offset: FileHeader::data_offset(version),
+            });
             // [Optimization] 提前获取文件长度，避免在循环中对每个 Entry 调用 syscall (stat)
             let file_len = reader.get_ref().reader.len()?;
-
-            // TODO: [File Header] Skip the fixed-length file header before reading entries.
-            // reader.seek_relative(HEADER_SIZE)?;
+            // WriteBatch 中的条目在读到提交标记后才写入索引
+            let mut replay = BatchReplay::new();
 
             loop {
                 let offset = reader.stream_position()?; // 记录起始位置
@@ -389,17 +708,7 @@ impl KVStore {
                         let body_len = 4 + header.val_len as u64;
 
                         if current_pos + body_len > file_len {
-                            eprintln!(
-                                "Recover: Incomplete entry body at file {} offset {}. Truncating.",
-                                file_id, offset
-                            );
-                            if is_active {
-                                self.writer.get_ref().set_len(offset)?;
-                                self.writer.set_offset(offset);
-                            } else {
-                                let write_file = self.fs.open_file(file_path)?;
-                                write_file.set_len(offset)?;
-                            }
+                            self.truncate_tail(*file_id, offset, "Incomplete entry body")?;
                             break;
                         }
 
@@ -407,16 +716,28 @@ impl KVStore {
                         // 使用 max 而不是直接赋值，是为了防止：
                         // 1. Compaction 产生的归档文件可能包含较旧的序列号，但文件 ID 较新。
                         // 2. 确保 next_seq_no 生成的序号永远大于数据库中已存在的任何序号。
-                        self.current_seq_no = self.current_seq_no.max(header.sequence_number);
-
-                        if header.is_tombstone() {
-                            self.indexer.remove(&header.key);
-                        } else {
-                            self.indexer.put(
-                                header.key,
-                                LogIndex::new(*file_id as u32, offset, header.val_len),
-                            );
-                        }
+                        self.keydir.current_seq_no =
+                            self.keydir.current_seq_no.max(header.sequence_number);
+
+                        let indexer = &mut self.keydir.indexer;
+                        replay.feed(
+                            header,
+                            |h| {
+                                (
+                                    h.is_tombstone(),
+                                    h.key,
+                                    LogIndex::new(*file_id, offset, h.val_len),
+                                )
+                            },
+                            |(tombstone, key, index)| {
+                                if tombstone {
+                                    indexer.remove(&key);
+                                } else {
+                                    indexer.put(key, index);
+                                }
+                                Ok::<_, TitaniumError>(())
+                            },
+                        )?;
 
                         // 关键优化：跳过 Value 部分 (BodyCRC 4 bytes + Value)
                         // 这样我们就不需要从磁盘读取 Value，大大加速启动
@@ -428,45 +749,998 @@ impl KVStore {
 
                     // 3. 数据损坏 (CRC 错, 意外EOF, Varint错, 数据超长) -> 执行截断
                     Err(e) => {
-                        let is_corruption = match &e {
-                            TitaniumError::CrcMismatch { .. }
-                            | TitaniumError::VarintDecodeError => true,
-                            TitaniumError::Io(io_e) => matches!(
-                                io_e.kind(),
-                                io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
-                            ),
-                            _ => false,
-                        };
-
-                        if !is_corruption {
+                        if !e.is_corruption() {
                             return Err(e);
                         }
 
-                        eprintln!(
-                            "Recover: Corrupted data at file {} offset {}. Truncating.",
-                            file_id, offset
-                        );
-
-                        if is_active {
-                            // 只有 active file (Storage) 才有 set_len 能力
-                            self.writer.get_ref().set_len(offset)?;
-                            // 关键修复：如果复用了 active file 且发生了截断，必须更新 writer 的 offset
-                            self.writer.set_offset(offset);
-                        } else {
-                            // 对于只读的归档文件，需要重新以写模式打开才能截断
-                            let write_file = self.fs.open_file(file_path)?;
-                            write_file.set_len(offset)?;
-                        }
-
+                        self.truncate_tail(*file_id, offset, "Corrupted data")?;
                         break; // 停止处理当前文件
                     }
                 }
             }
+
+            let dropped = replay.finish();
+            if dropped > 0 {
+                eprintln!(
+                    "Recover: Dropped {} entries of uncommitted write batches in file {}.",
+                    dropped, file_id
+                );
+            }
+        }
+
+        // 新的写入从恢复出的最大序列号之后继续分配
+        if let Some(active) = &mut self.active {
+            active.seq_no = self.keydir.current_seq_no;
         }
         Ok(())
     }
 }
 
+impl Deref for KVStore {
+    type Target = KeyDir;
+
+    fn deref(&self) -> &KeyDir {
+        &self.keydir
+    }
+}
+
+impl KeyDir {
+    /// 是否以只读模式打开
+    pub fn is_read_only(&self) -> bool {
+        self.active_file.is_none()
+    }
+
+    /// 写入背压：待合并文件数 >= write_stall_threshold 时，写入前需要休眠的时长
+    ///
+    /// 休眠在获取写锁之前进行，只拖慢发起写入的调用者，不阻塞其他读写。
+    /// 达到 write_stop_threshold 时不休眠，由写入时的同步合并处理。
+    pub(crate) fn stall_delay(&self) -> Option<Duration> {
+        let (stall, stop) = self.config.write_thresholds();
+        let pending = self.pending_files;
+        if self.is_read_only() || pending < stall || pending >= stop {
+            return None;
+        }
+        Some(WRITE_STALL_DELAY * (pending - stall + 1) as u32)
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<LogEntry>, TitaniumError> {
+        match self.indexer.get(key.as_ref()) {
+            Some(index) => self.read_entry(index),
+            None => Ok(None),
+        }
+    }
+
+    /// 按 Key 升序扫描范围内的数据，Value 在访问 `ScanEntry::value` 时才从磁盘读取
+    ///
+    /// 要求打开时选择有序索引，否则返回 `Unsupported`。
+    pub fn scan<K, R>(&self, range: R) -> Result<ScanIter<'_>, TitaniumError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let lower = range.start_bound().map(|k| k.as_ref());
+        let upper = range.end_bound().map(|k| k.as_ref());
+        let inner = self
+            .indexer
+            .range(lower, upper)
+            .ok_or(TitaniumError::Unsupported(
+                "range scan requires an ordered indexer",
+            ))?;
+        Ok(ScanIter { store: self, inner })
+    }
+
+    /// 扫描所有以 `prefix` 开头的 Key
+    pub fn scan_prefix(&self, prefix: impl AsRef<[u8]>) -> Result<ScanIter<'_>, TitaniumError> {
+        let prefix = prefix.as_ref();
+        let upper = prefix_upper_bound(prefix);
+        let upper = match &upper {
+            Some(upper) => Bound::Excluded(upper.as_slice()),
+            None => Bound::Unbounded,
+        };
+        self.scan::<[u8], _>((Bound::Included(prefix), upper))
+    }
+
+    /// 遍历所有未过期的 Key，顺序由索引决定 (BTree 索引为升序，Hash 索引无序)
+    ///
+    /// 索引中不记录过期时间，为了跳过过期数据，每个 Key 都需要从磁盘读取一次条目 Header (不读取 Value)。
+    pub fn keys(&self) -> Keys<'_> {
+        Keys {
+            store: self,
+            inner: self.indexer.iter(),
+        }
+    }
+
+    /// 遍历所有未过期的条目，Value 在迭代到该条目时才从磁盘解码
+    ///
+    /// 条目所在文件在读取时才根据 file_id 解析 (活跃文件或 file_map 中的归档文件)，
+    /// 因此活跃文件被轮转为归档文件后，已经取得的索引仍然有效。
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            store: self,
+            inner: self.indexer.iter(),
+        }
+    }
+
+    /// 统计运行状态；不遍历索引，也不读取数据文件中的条目
+    pub fn stats(&self) -> Result<Stats, TitaniumError> {
+        let mut disk_usage = 0;
+        for file in self.file_map.values() {
+            disk_usage += file.reader.len()?;
+        }
+        if let Some((_, reader)) = &self.active_file {
+            disk_usage += reader.len()?;
+        }
+        Ok(Stats {
+            keys: self.indexer.len(),
+            data_files: self.file_map.len() + usize::from(self.active_file.is_some()),
+            disk_usage,
+            pending_compaction_files: self.pending_files,
+            sequence_number: self.current_seq_no,
+            read_only: self.is_read_only(),
+        })
+    }
+
+    /// 创建一个一致性读快照，固定当前的序列号
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshots.acquire(self.current_seq_no)
+    }
+
+    /// 读取快照创建时 Key 的值
+    pub fn get_at(
+        &self,
+        snapshot: &Snapshot,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<LogEntry>, TitaniumError> {
+        match self.index_at(snapshot.sequence_number(), key.as_ref()) {
+            Some(index) => self.read_entry(index),
+            None => Ok(None),
+        }
+    }
+
+    /// 按 Key 升序遍历快照中所有未过期的条目
+    ///
+    /// 创建迭代器时会收集快照可见的全部 (Key, 索引)，Value 仍在迭代时才从磁盘解码。
+    pub fn iter_at(&self, snapshot: &Snapshot) -> SnapshotIter<'_> {
+        let seq = snapshot.sequence_number();
+        let mut visible: Vec<(Vec<u8>, LogIndex)> = self
+            .indexer
+            .iter()
+            .filter_map(|(key, index)| {
+                let index = self.history.lookup(key, seq).unwrap_or(Some(index))?;
+                Some((key.to_vec(), index))
+            })
+            .collect();
+        // 快照之后被删除的 Key 只存在于版本历史中
+        for key in self.history.keys() {
+            if self.indexer.get(key).is_none()
+                && let Some(Some(index)) = self.history.lookup(key, seq)
+            {
+                visible.push((key.to_vec(), index));
+            }
+        }
+        visible.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        SnapshotIter {
+            store: self,
+            inner: visible.into_iter(),
+        }
+    }
+
+    /// 开始一个乐观事务，读取基于当前时刻的快照
+    pub fn begin(&self) -> Transaction {
+        Transaction::new(self.snapshot())
+    }
+
+    /// 序列号为 seq 的快照中 Key 对应的索引
+    pub(crate) fn index_at(&self, seq: u64, key: &[u8]) -> Option<LogIndex> {
+        match self.history.lookup(key, seq) {
+            Some(prev) => prev,
+            None => self.indexer.get(key),
+        }
+    }
+
+    /// 根据索引读取并解码一个条目，已过期时返回 None
+    pub(crate) fn read_entry(
+        &self,
+        log_index: LogIndex,
+    ) -> Result<Option<LogEntry>, TitaniumError> {
+        let entry = self.decode_at(log_index, |decoder, reader| decoder.decode_from(reader))?;
+
+        // [TTL Check] 检查数据是否过期
+        if is_expired(entry.expire_at()) {
+            // 数据已过期，返回 None (惰性删除：索引中还在，但用户读不到)
+            return Ok(None);
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Key 当前未过期版本的序列号，不存在或已过期时返回 None
+    pub(crate) fn current_version(&self, key: &[u8]) -> Result<Option<u64>, TitaniumError> {
+        let Some(index) = self.indexer.get(key) else {
+            return Ok(None);
+        };
+        let header = self.read_header(index)?;
+        Ok((!is_expired(header.expire_at)).then_some(header.sequence_number))
+    }
+
+    /// 只读取条目的 Header 和 Key，不读取 Value
+    fn read_header(&self, log_index: LogIndex) -> Result<LogHeader, TitaniumError> {
+        self.decode_at(log_index, |decoder, reader| {
+            decoder.decode_header_and_key(reader)
+        })
+    }
+
+    /// 在索引指向的位置上运行解码函数
+    fn decode_at<T>(
+        &self,
+        log_index: LogIndex,
+        f: impl FnOnce(&mut Decoder, &mut FileAtReader) -> Result<Option<T>, TitaniumError>,
+    ) -> Result<T, TitaniumError> {
+        // 区分读取的是归档文件还是当前的活跃文件
+        // 活跃文件不在 file_map 中，通过 open/rotate 时单独打开的只读句柄读取，不与 Writer 共享游标
+        let (reader, version): (&dyn RandomAccessFile, u8) = match &self.active_file {
+            Some((id, reader)) if log_index.file_id == *id => {
+                (reader.as_ref(), CURRENT_FORMAT_VERSION)
+            }
+            _ => {
+                let file = &self.file_map[&log_index.file_id];
+                (file.reader.as_ref(), file.version)
+            }
+        };
+
+        // 使用 FileAtReader 替代 seek，实现无锁并发读取
+        let mut reader = FileAtReader {
+            reader,
+            offset: log_index.offset,
+        };
+
+        // 使用 Thread-local Decoder 减少高频读取时的内存分配开销
+        thread_local! {
+            static DECODER: std::cell::RefCell<Decoder> = std::cell::RefCell::new(Decoder::new(0, 0));
+        }
+
+        DECODER.with(|cell| {
+            let mut decoder = cell.borrow_mut();
+            let (max_key, max_val) = self.config.max_sizes();
+            decoder.set_limits(max_key, max_val);
+            decoder.set_version(version);
+            f(&mut decoder, &mut reader)?.ok_or_else(|| {
+                TitaniumError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Unexpected EOF at indexed offset",
+                ))
+            })
+        })
+    }
+
+    /// 从 Hint 文件恢复一个归档文件的索引，返回 false 表示需要全量扫描
+    fn load_hint(&mut self, file_id: u32) -> Result<bool, TitaniumError> {
+        let data_len = self.file_map[&file_id].reader.len()?;
+        let (max_key, _) = self.config.max_sizes();
+        let indexer = &mut self.indexer;
+        let current_seq_no = &mut self.current_seq_no;
+
+        hint::read_hint_file(
+            self.fs.as_ref(),
+            &self.data_path,
+            file_id,
+            data_len,
+            max_key,
+            |entry| {
+                *current_seq_no = (*current_seq_no).max(entry.sequence_number);
+                if entry.tombstone {
+                    indexer.remove(&entry.key);
+                } else {
+                    indexer.put(
+                        entry.key,
+                        LogIndex::new(file_id, entry.offset, entry.val_len),
+                    );
+                }
+            },
+        )
+    }
+
+    /// 更新索引；存在活跃快照时，先把被覆盖的版本记入版本历史
+    fn index_put(&mut self, key: Vec<u8>, index: LogIndex, seq_no: u64) {
+        self.record_history(&key, seq_no);
+        self.indexer.put(key, index);
+        self.current_seq_no = seq_no;
+    }
+
+    fn index_remove(&mut self, key: &[u8], seq_no: u64) {
+        self.record_history(key, seq_no);
+        self.indexer.remove(key);
+        self.current_seq_no = seq_no;
+    }
+
+    fn record_history(&mut self, key: &[u8], seq_no: u64) {
+        let oldest = self.snapshots.oldest();
+        self.history.prune(oldest);
+        if oldest.is_some() {
+            self.history.record(key, seq_no, self.indexer.get(key));
+        }
+    }
+
+    /// 轮转后发布：旧活跃文件转为归档文件，读路径切换到新的活跃文件
+    fn seal(&mut self, old: (u32, DataFile), active: ActiveReader) {
+        self.file_map.insert(old.0, old.1);
+        self.pending_files += 1;
+        self.active_file = Some(active);
+    }
+}
+
+impl ActiveFile {
+    fn new(
+        writer: Writer<Box<dyn Storage>>,
+        id: u32,
+        fs: Arc<dyn FileSystem>,
+        data_path: PathBuf,
+        config: config::ConfigWatcher,
+    ) -> Self {
+        Self {
+            writer,
+            id,
+            fs,
+            data_path,
+            config,
+            seq_no: 0,
+            last_compaction: None,
+            // 首次写入时立即检查
+            bytes_since_space_check: DISK_CHECK_INTERVAL_BYTES,
+            sync_file: None,
+            group_commit: false,
+            pending_sync: 0,
+            syncer: None,
+            closed: false,
+        }
+    }
+
+    /// 获取下一个序列号，如果溢出则返回错误
+    fn next_seq_no(&mut self) -> Result<u64, TitaniumError> {
+        self.seq_no = self.seq_no.checked_add(1).ok_or_else(|| {
+            TitaniumError::Io(io::Error::other(
+                "Sequence number overflow: database limit reached",
+            ))
+        })?;
+        Ok(self.seq_no)
+    }
+
+    /// 追加一个条目，返回它的起始偏移
+    fn append(&mut self, entry: &LogEntry) -> Result<u64, TitaniumError> {
+        let offset = self.writer.write_entry(entry)?;
+        self.bytes_since_space_check += self.writer.current_offset() - offset;
+        Ok(offset)
+    }
+
+    /// 按 write_mod 持久化刚追加的条目
+    ///
+    /// - 开启组提交后，Sync 模式只把数据推给内核并记录待同步的序列号，由 SharedKVStore 合并 fsync；
+    /// - Interval 模式只把数据推给内核，由后台线程周期性 fsync。
+    ///
+    /// 每种模式都至少推给内核，发布到索引之后读路径的独立句柄能立即读到。
+    fn persist(&mut self) -> Result<(), TitaniumError> {
+        match self.config.write_mod() {
+            config::WriteMod::Sync if self.group_commit => {
+                self.writer.flush_to_os()?;
+                self.pending_sync = self.seq_no;
+            }
+            config::WriteMod::Sync => self.writer.sync()?,
+            config::WriteMod::Buffer => self.writer.flush_to_os()?,
+            config::WriteMod::Interval(_) => {
+                self.writer.flush_to_os()?;
+                if self.syncer.is_none() {
+                    let file = self.open_sync_file()?;
+                    self.syncer = Some(BackgroundSyncer::spawn(self.config.clone(), file));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 为活跃文件单独打开一个同步句柄 (已打开时直接返回)，使 fsync 可以在不持有写锁的情况下进行
+    fn open_sync_file(&mut self) -> Result<SyncFile, TitaniumError> {
+        if let Some(file) = &self.sync_file {
+            return Ok(file.clone());
+        }
+        let path = self.data_path.join(format!("{:04}.bs", self.id));
+        let file: SyncFile = Arc::new(Mutex::new(self.fs.open_file(&path)?));
+        self.sync_file = Some(file.clone());
+        Ok(file)
+    }
+
+    /// 开启组提交，返回组提交使用的活跃文件句柄，见 `SharedKVStore`
+    ///
+    /// 轮转时旧文件已经同步，句柄原地切换到新文件，因此只需同步这一个句柄。
+    pub(crate) fn enable_group_commit(&mut self) -> Result<SyncFile, TitaniumError> {
+        let file = self.open_sync_file()?;
+        self.group_commit = true;
+        Ok(file)
+    }
+
+    /// 组提交模式下最后一个等待 fsync 的写入的序列号
+    pub(crate) fn pending_sync(&self) -> u64 {
+        self.pending_sync
+    }
+
+    /// 磁盘空间保护：可用空间低于 min_free_space 时拒绝写入
+    ///
+    /// 为避免每次写入都产生 statvfs 系统调用，只在累计写入 DISK_CHECK_INTERVAL_BYTES 后检查一次。
+    /// 检查失败时不重置计数，后续写入会持续检查，直到空间恢复。
+    /// 删除操作 (墓碑) 不受限制，以便用户能够通过删除 + 合并释放空间。
+    fn check_disk_space(&mut self) -> Result<(), TitaniumError> {
+        if self.bytes_since_space_check < DISK_CHECK_INTERVAL_BYTES {
+            return Ok(());
+        }
+        let required = self.config.min_free_space();
+        let available = self.fs.available_space(&self.data_path)?;
+        if available < required {
+            return Err(TitaniumError::DiskFull {
+                available,
+                required,
+            });
+        }
+        self.bytes_since_space_check = 0;
+        Ok(())
+    }
+
+    /// 轮转活跃文件：封存当前文件并创建新的活跃文件
+    ///
+    /// 返回封存的文件和新活跃文件的只读句柄，由调用者发布到 KeyDir。
+    fn rotate(&mut self) -> Result<((u32, DataFile), ActiveReader), TitaniumError> {
+        // 0. 新文件会继续占用磁盘，轮转前强制检查可用空间
+        self.bytes_since_space_check = DISK_CHECK_INTERVAL_BYTES;
+        self.check_disk_space()?;
+
+        // 1. 强制刷盘，确保旧数据落盘
+        self.writer.sync()?;
+
+        // 2. 重新以只读模式打开旧文件，作为归档文件供 get 使用
+        let old_id = self.id;
+        let old_path = self.data_path.join(format!("{:04}.bs", old_id));
+        let old_file = DataFile {
+            reader: self.fs.open_reader(&old_path)?,
+            path: old_path,
+            version: CURRENT_FORMAT_VERSION,
+        };
+
+        // 为封存的文件生成 Hint 文件，加速下次启动；失败时仅记录日志，restore 会回退到全量扫描
+        let (max_key, max_val) = self.config.max_sizes();
+        let mut decoder = Decoder::new(max_key, max_val);
+        if let Err(e) = hint::write_hint_from_data(
+            self.fs.as_ref(),
+            &self.data_path,
+            old_id,
+            old_file.reader.as_ref(),
+            &mut decoder,
+        ) {
+            eprintln!("Rotate: Failed to write hint file for {}: {}", old_id, e);
+        }
+
+        // 3. 更新 ID 并创建新文件
+        // Writer::create 会写入文件头，offset 从文件头之后开始
+        self.id += 1;
+        let new_path = self.data_path.join(format!("{:04}.bs", self.id));
+        self.writer = Writer::create(self.fs.create_file(&new_path)?)?;
+        let new_reader = self.fs.open_reader(&new_path)?;
+        // 同步句柄原地替换，后台同步线程和组提交的领导者随之切换到新文件
+        if let Some(file) = &self.sync_file {
+            *file.lock() = self.fs.open_file(&new_path)?;
+        }
+
+        Ok(((old_id, old_file), (self.id, new_reader)))
+    }
+
+    /// 刷新缓冲区并 fsync 活跃文件，见 `KVStore::sync`
+    pub(crate) fn sync(&mut self) -> Result<(), TitaniumError> {
+        self.writer.sync()
+    }
+
+    /// 关闭活跃文件：停止后台同步线程，刷新缓冲区并 fsync
+    pub(crate) fn close(mut self) -> Result<(), TitaniumError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), TitaniumError> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        // 先停止后台同步线程 (drop 时会等待线程退出)，避免与最后一次 fsync 并发
+        self.syncer.take();
+        let result = self.writer.sync();
+        self.sync_file.take();
+        result
+    }
+}
+
+impl Drop for ActiveFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            eprintln!("Close: Failed to sync data on drop: {}", e);
+        }
+    }
+}
+
+/// 写入时访问 KeyDir 的方式：独占的 KVStore 直接借用，SharedKVStore 通过读写锁
+enum KeyDirRef<'a> {
+    Owned(&'a mut KeyDir),
+    Shared(&'a RwLock<KeyDir>),
+}
+
+impl KeyDirRef<'_> {
+    fn read<T>(&self, f: impl FnOnce(&KeyDir) -> T) -> T {
+        match self {
+            KeyDirRef::Owned(keydir) => f(keydir),
+            KeyDirRef::Shared(lock) => f(&lock.read()),
+        }
+    }
+
+    fn write<T>(&mut self, f: impl FnOnce(&mut KeyDir) -> T) -> T {
+        match self {
+            KeyDirRef::Owned(keydir) => f(keydir),
+            KeyDirRef::Shared(lock) => f(&mut lock.write()),
+        }
+    }
+}
+
+/// 写入句柄：独占活跃文件，写入追加完成后才短暂地获取 KeyDir 的写锁发布索引
+///
+/// `KVStore` 的写接口通过它实现；`SharedKVStore::write` 把它传给闭包，闭包执行期间其他写者等待，
+/// 读者不受影响 (合并时也只在最后替换 file_map 和索引时短暂阻塞读者)。
+pub struct WriteHandle<'a> {
+    active: &'a mut ActiveFile,
+    keydir: KeyDirRef<'a>,
+}
+
+impl<'a> WriteHandle<'a> {
+    pub(crate) fn shared(active: &'a mut ActiveFile, keydir: &'a RwLock<KeyDir>) -> Self {
+        Self {
+            active,
+            keydir: KeyDirRef::Shared(keydir),
+        }
+    }
+}
+
+impl WriteHandle<'_> {
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<LogEntry>, TitaniumError> {
+        self.keydir.read(|keydir| keydir.get(key))
+    }
+
+    pub(crate) fn current_version(&self, key: &[u8]) -> Result<Option<u64>, TitaniumError> {
+        self.keydir.read(|keydir| keydir.current_version(key))
+    }
+
+    /// 见 `KVStore::set`
+    pub fn set(&mut self, key: impl Into<Vec<u8>>, value: Vec<u8>) -> Result<(), TitaniumError> {
+        self.put(key.into(), value, None)
+    }
+
+    /// 见 `KVStore::set_with_ttl`
+    pub fn set_with_ttl(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<(), TitaniumError> {
+        self.put(key.into(), value, Some(ttl))
+    }
+
+    fn put(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), TitaniumError> {
+        // 0. 写入背压 & 磁盘空间检查，检查是否需要轮转文件
+        self.check_write_pressure()?;
+        self.active.check_disk_space()?;
+        self.rotate_if_full()?;
+
+        let expire_at = ttl.map(|ttl| expire_at(now_millis(), ttl)).transpose()?;
+        let seq_no = self.active.next_seq_no()?;
+
+        // 1. write to log file
+        let builder = LogEntry::new(key, value, seq_no);
+        let entry = match expire_at {
+            Some(expire_at) => builder.with_ttl(expire_at).build(),
+            None => builder.build(),
+        };
+        let offset = self.active.append(&entry)?;
+        // use config to decide when to sync
+        self.active.persist()?;
+        // 2. update indexer
+        let index = LogIndex::new(self.active.id, offset, entry.value.len() as u32);
+        self.keydir
+            .write(|keydir| keydir.index_put(entry.key, index, seq_no));
+        Ok(())
+    }
+
+    /// 见 `KVStore::remove`
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Result<(), TitaniumError> {
+        let key = key.as_ref();
+        // 1. 如果 Key 存在，则写入 Tombstone
+        if self.keydir.read(|keydir| keydir.indexer.get(key).is_some()) {
+            self.check_write_pressure()?;
+            // 轮转前会检查磁盘空间；空间不足时墓碑继续追加到当前文件，保证删除总能执行
+            match self.rotate_if_full() {
+                Err(TitaniumError::DiskFull { .. }) => {}
+                result => result?,
+            }
+            let seq_no = self.active.next_seq_no()?;
+
+            // 墓碑不检查磁盘空间，但同样计入写入字节数，后续写入按时重新检查
+            let entry = LogEntry::new_tombstone(key, seq_no);
+            self.active.append(&entry)?;
+            self.active.persist()?;
+            // 2. 从内存索引中移除
+            self.keydir.write(|keydir| keydir.index_remove(key, seq_no));
+        }
+        Ok(())
+    }
+
+    /// 见 `KVStore::compare_and_swap`
+    pub fn compare_and_swap(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        let key = key.into();
+        let current = self.get(&key)?;
+        if current.as_ref().map(|entry| entry.value.as_slice()) != expected {
+            return Ok(false);
+        }
+        self.set(key, new)?;
+        Ok(true)
+    }
+
+    /// 见 `KVStore::set_if_absent`
+    pub fn set_if_absent(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        let key = key.into();
+        if self.current_version(&key)?.is_some() {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
+
+    /// 见 `KVStore::set_if_present`
+    pub fn set_if_present(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        let key = key.into();
+        if self.current_version(&key)?.is_none() {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
+
+    /// 见 `KVStore::set_if_version`
+    pub fn set_if_version(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        expected: u64,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        let key = key.into();
+        if self.current_version(&key)? != Some(expected) {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
+
+    /// 见 `KVStore::write_batch`
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<(), TitaniumError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let count = u32::try_from(batch.len()).map_err(|_| {
+            TitaniumError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Write batch has too many operations",
+            ))
+        })?;
+
+        // 先计算所有过期时间，TTL 超出上限时批次中的条目都不会写入
+        let now = now_millis();
+        let ops = batch
+            .into_ops()
+            .into_iter()
+            .map(|op| {
+                let expire_at = match &op {
+                    BatchOp::Put { ttl: Some(ttl), .. } => Some(expire_at(now, *ttl)?),
+                    _ => None,
+                };
+                Ok((op, expire_at))
+            })
+            .collect::<Result<Vec<_>, TitaniumError>>()?;
+
+        // 0. 写入背压 & 磁盘空间检查；批次不跨文件，因此只在写入前检查是否需要轮转
+        self.check_write_pressure()?;
+        self.active.check_disk_space()?;
+        self.rotate_if_full()?;
+
+        let start_offset = self.active.writer.current_offset();
+
+        // 1. 写入批次条目，记录它们的位置 (Key, 非墓碑时的索引)
+        let mut applied: Vec<(Vec<u8>, Option<LogIndex>, u64)> = Vec::with_capacity(ops.len());
+        for (op, expire_at) in ops {
+            let seq_no = self.active.next_seq_no()?;
+            let mut entry = match op {
+                BatchOp::Put { key, value, .. } => {
+                    let builder = LogEntry::new(key, value, seq_no);
+                    match expire_at {
+                        Some(expire_at) => builder.with_ttl(expire_at).build(),
+                        None => builder.build(),
+                    }
+                }
+                BatchOp::Delete { key } => LogEntry::new_tombstone(key, seq_no),
+            };
+            entry.mark_batch();
+            let offset = self.active.writer.write_entry(&entry)?;
+            let index = (!entry.is_tombstone())
+                .then(|| LogIndex::new(self.active.id, offset, entry.value.len() as u32));
+            applied.push((entry.key, index, seq_no));
+        }
+
+        // 2. 写入提交标记，序列号与最后一个条目相同
+        let commit = LogEntry::new_batch_commit(count, self.active.seq_no);
+        self.active.writer.write_entry(&commit)?;
+        self.active.bytes_since_space_check += self.active.writer.current_offset() - start_offset;
+        self.active.persist()?;
+
+        // 3. 批次已完整写入，在同一次加锁内统一更新索引，读者不会看到一半的批次
+        self.keydir.write(|keydir| {
+            for (key, index, seq_no) in applied {
+                match index {
+                    Some(index) => keydir.index_put(key, index, seq_no),
+                    None => keydir.index_remove(&key, seq_no),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// 见 `KVStore::commit`
+    ///
+    /// 写者互斥，校验通过后到写入完成之前不会有其他写入发布。
+    pub fn commit(&mut self, txn: Transaction) -> Result<(), TitaniumError> {
+        let batch = self.keydir.read(|keydir| txn.validate(keydir))?;
+        self.write_batch(batch)
+    }
+
+    /// 见 `KVStore::compact`
+    ///
+    /// 合并文件的扫描和写入只持有 KeyDir 的读锁，读者可以继续读取旧文件；
+    /// 只有最后替换 file_map 和索引时获取写锁。
+    pub fn compact(&mut self) -> Result<(), TitaniumError> {
+        self.active.last_compaction = Some(Instant::now());
+        // 活跃快照仍需要的旧版本也必须保留
+        self.keydir
+            .write(|keydir| keydir.history.prune(keydir.snapshots.oldest()));
+        let merge = self.keydir.read(Compacter::merge)?;
+        self.keydir.write(|keydir| {
+            if let Some(merge) = merge {
+                Compacter::install(keydir, merge);
+            }
+            keydir.pending_files = 0;
+        });
+        Ok(())
+    }
+
+    /// 见 `KVStore::sync`
+    pub fn sync(&mut self) -> Result<(), TitaniumError> {
+        self.active.sync()
+    }
+
+    fn rotate_if_full(&mut self) -> Result<(), TitaniumError> {
+        if self.active.writer.current_offset() >= self.active.config.max_file_size() as u64 {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// 轮转活跃文件：将当前文件转为只读归档，并创建新的活跃文件
+    fn rotate(&mut self) -> Result<(), TitaniumError> {
+        let (old, active) = self.active.rotate()?;
+        self.keydir.write(|keydir| keydir.seal(old, active));
+        // 归档文件过多时触发合并
+        self.maybe_compact()
+    }
+
+    /// 待合并文件数达到 compaction_threshold 时合并，两次自动合并至少间隔 compaction_check_interval_ms
+    fn maybe_compact(&mut self) -> Result<(), TitaniumError> {
+        let cfg = self.active.config.get();
+        if self.pending_files() < cfg.compaction_threshold {
+            return Ok(());
+        }
+        let interval = Duration::from_millis(cfg.compaction_check_interval_ms);
+        if self
+            .active
+            .last_compaction
+            .is_some_and(|last| last.elapsed() < interval)
+        {
+            return Ok(());
+        }
+        self.compact()
+    }
+
+    fn pending_files(&self) -> usize {
+        self.keydir.read(|keydir| keydir.pending_files)
+    }
+
+    /// 写入背压：待合并文件数 >= write_stop_threshold 时阻塞当前写入并立即执行合并 (忽略合并间隔)，
+    /// 合并失败或仍未降到阈值以下时返回 `SystemOverload`
+    ///
+    /// stall 阶段的休眠见 `KeyDir::stall_delay`，由调用者在获取写锁之前执行。
+    fn check_write_pressure(&mut self) -> Result<(), TitaniumError> {
+        let (_, stop) = self.active.config.write_thresholds();
+
+        if self.pending_files() >= stop {
+            if let Err(e) = self.compact() {
+                eprintln!("Write stopped: compaction failed: {}", e);
+                return Err(TitaniumError::SystemOverload);
+            }
+            if self.pending_files() >= stop {
+                return Err(TitaniumError::SystemOverload);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 当前时间 (毫秒)
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// 根据 TTL 计算过期时间 (毫秒)，TTL 超过 `MAX_TTL` 时返回 `InvalidInput`
+fn expire_at(now: u64, ttl: Duration) -> Result<u64, TitaniumError> {
+    Some(ttl)
+        .filter(|ttl| *ttl <= MAX_TTL)
+        .and_then(|ttl| now.checked_add(ttl.as_millis() as u64))
+        .ok_or_else(|| {
+            TitaniumError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "TTL is too large",
+            ))
+        })
+}
+
+/// 当前时间 (毫秒) 是否已超过过期时间
+fn is_expired(expire_at: Option<u64>) -> bool {
+    expire_at.is_some_and(|expire_at| now_millis() > expire_at)
+}
+
+/// `KVStore::keys` 返回的迭代器
+pub struct Keys<'a> {
+    store: &'a KeyDir,
+    inner: IndexIter<'a>,
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = Result<&'a [u8], TitaniumError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (key, index) in self.inner.by_ref() {
+            match self.store.read_header(index) {
+                Ok(header) if is_expired(header.expire_at) => continue,
+                Ok(_) => return Some(Ok(key)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// `KVStore::iter` 返回的迭代器
+pub struct Iter<'a> {
+    store: &'a KeyDir,
+    inner: IndexIter<'a>,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Result<LogEntry, TitaniumError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (_, index) in self.inner.by_ref() {
+            match self.store.read_entry(index) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// `KVStore::iter_at` 返回的迭代器
+pub struct SnapshotIter<'a> {
+    store: &'a KeyDir,
+    inner: std::vec::IntoIter<(Vec<u8>, LogIndex)>,
+}
+
+impl Iterator for SnapshotIter<'_> {
+    type Item = Result<LogEntry, TitaniumError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (_, index) in self.inner.by_ref() {
+            match self.store.read_entry(index) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// 前缀扫描的上界：最小的大于所有以 prefix 开头的 Key 的字节串
+///
+/// 去掉末尾的 0xff 后将最后一个字节加一；prefix 全为 0xff (或为空) 时没有上界。
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
+
+/// `KVStore::scan` 返回的迭代器，按 Key 升序产出 `ScanEntry`
+pub struct ScanIter<'a> {
+    store: &'a KeyDir,
+    inner: IndexIter<'a>,
+}
+
+impl<'a> Iterator for ScanIter<'a> {
+    type Item = ScanEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, index)| ScanEntry {
+            store: self.store,
+            key,
+            index,
+        })
+    }
+}
+
+/// 扫描结果中的一项：Key 直接来自索引，Value 延迟解码
+pub struct ScanEntry<'a> {
+    store: &'a KeyDir,
+    key: &'a [u8],
+    index: LogIndex,
+}
+
+impl<'a> ScanEntry<'a> {
+    pub fn key(&self) -> &'a [u8] {
+        self.key
+    }
+
+    /// 从磁盘读取完整条目，已过期时返回 None
+    pub fn entry(&self) -> Result<Option<LogEntry>, TitaniumError> {
+        self.store.read_entry(self.index)
+    }
+
+    /// 从磁盘读取 Value，已过期时返回 None
+    pub fn value(&self) -> Result<Option<Vec<u8>>, TitaniumError> {
+        Ok(self.entry()?.map(|entry| entry.value))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -492,28 +1766,116 @@ mod tests {
 
         // 1. Set & Get
         kv.set("key1".to_string(), b"value1".to_vec()).unwrap();
-        let entry = kv.get("key1".to_string()).unwrap().unwrap();
+        let entry = kv.get("key1").unwrap().unwrap();
         assert_eq!(entry.value, b"value1");
-        assert_eq!(entry.key, "key1");
+        assert_eq!(entry.key, b"key1");
 
         // 2. Update (Overwrite)
         kv.set("key1".to_string(), b"value2".to_vec()).unwrap();
-        let entry = kv.get("key1".to_string()).unwrap().unwrap();
+        let entry = kv.get("key1").unwrap().unwrap();
         assert_eq!(entry.value, b"value2");
 
         // 3. Remove
         kv.remove("key1").unwrap();
-        assert!(kv.get("key1".to_string()).unwrap().is_none());
+        assert!(kv.get("key1").unwrap().is_none());
 
         // 4. Get non-existent
-        assert!(kv.get("key_not_found".to_string()).unwrap().is_none());
+        assert!(kv.get("key_not_found").unwrap().is_none());
 
         // 5. Empty Key/Value
         kv.set("".to_string(), vec![]).unwrap();
-        let entry = kv.get("".to_string()).unwrap().unwrap();
+        let entry = kv.get("").unwrap().unwrap();
         assert_eq!(entry.value.len(), 0);
     }
 
+    #[test]
+    fn test_interval_write_mod() {
+        let (mut kv, _, watcher) = create_kv_store("test_interval");
+        let mut cfg = watcher.get();
+        cfg.write_mod = config::WriteMod::Interval(5);
+        cfg.max_file_size = 200;
+        watcher.override_config(cfg);
+
+        assert!(kv.active.as_ref().unwrap().syncer.is_none());
+        kv.set("k0", b"v0".to_vec()).unwrap();
+        assert!(kv.active.as_ref().unwrap().syncer.is_some());
+
+        // 轮转后同步句柄切换到新的活跃文件，写入照常可读
+        for i in 1..20 {
+            kv.set(format!("k{}", i), vec![b'x'; 32]).unwrap();
+        }
+        assert!(kv.active.as_ref().unwrap().id > 1);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(kv.get("k0").unwrap().unwrap().value, b"v0");
+        assert_eq!(kv.get("k19").unwrap().unwrap().value, vec![b'x'; 32]);
+    }
+
+    #[test]
+    fn test_directory_lock() {
+        let (kv, fs, watcher) = create_kv_store("test_lock");
+        assert!(fs.exists(&Path::new("test_lock").join(LOCK_FILE)));
+
+        // 同一目录不能被第二个实例打开
+        match KVStore::new(watcher.clone(), fs.clone()) {
+            Err(TitaniumError::DirectoryLocked(path)) => assert_eq!(path, Path::new("test_lock")),
+            other => panic!("Expected DirectoryLocked, got {:?}", other.err()),
+        }
+
+        // 释放后可以重新打开
+        drop(kv);
+        KVStore::new(watcher, fs).unwrap();
+    }
+
+    #[test]
+    fn test_close() {
+        let (mut kv, fs, watcher) = create_kv_store("test_close");
+        let mut cfg = watcher.get();
+        cfg.write_mod = config::WriteMod::Interval(60_000);
+        watcher.override_config(cfg);
+        kv.set("k1", b"v1".to_vec()).unwrap();
+        assert!(kv.active.as_ref().unwrap().syncer.is_some());
+
+        // close 停止后台线程 (否则会阻塞一个同步周期) 并释放目录锁
+        let start = Instant::now();
+        kv.close().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.get("k1").unwrap().unwrap().value, b"v1");
+        kv.close().unwrap();
+
+        // 只读实例同样可以关闭
+        let kv = KVStore::builder(watcher, fs).read_only().open().unwrap();
+        kv.close().unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let (mut kv, _, _) = create_kv_store("test_stats");
+        kv.set("k1", b"v1".to_vec()).unwrap();
+        kv.set("k2", b"v2".to_vec()).unwrap();
+        kv.set_with_ttl("ttl", b"v".to_vec(), Duration::from_millis(1))
+            .unwrap();
+        kv.remove("k2").unwrap();
+        thread::sleep(Duration::from_millis(10));
+
+        let stats = kv.stats().unwrap();
+        // 过期的 Key 在合并清理之前仍然计数
+        assert_eq!(stats.keys, 2);
+        assert_eq!(stats.data_files, 1);
+        assert_eq!(stats.sequence_number, 4);
+        assert_eq!(
+            stats.disk_usage,
+            kv.active.as_ref().unwrap().writer.current_offset()
+        );
+        assert!(!stats.read_only);
+
+        kv.handle().unwrap().rotate().unwrap();
+        kv.compact().unwrap();
+        assert_eq!(kv.stats().unwrap().keys, 1);
+    }
+
     #[test]
     fn test_ttl_expiration() {
         let (mut kv, _, _) = create_kv_store("test_ttl");
@@ -523,14 +1885,34 @@ mod tests {
             .unwrap();
 
         // 立即读取 (未过期)
-        assert!(kv.get("key_ttl".to_string()).unwrap().is_some());
+        assert!(kv.get("key_ttl").unwrap().is_some());
 
         // 等待过期
         thread::sleep(Duration::from_millis(200));
 
         // 再次读取 (已过期)
         // 注意：底层数据还在磁盘上，但 get 接口会过滤掉
-        assert!(kv.get("key_ttl".to_string()).unwrap().is_none());
+        assert!(kv.get("key_ttl").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ttl_overflow_rejected() {
+        let (mut kv, _, _) = create_kv_store("test_ttl_overflow");
+        let huge = Duration::from_millis(u64::MAX);
+
+        let err = kv.set_with_ttl("k", b"v".to_vec(), huge).unwrap_err();
+        assert!(matches!(err, TitaniumError::Io(e) if e.kind() == io::ErrorKind::InvalidInput));
+        let mut batch = WriteBatch::new();
+        batch
+            .put("a", b"1".to_vec())
+            .put_with_ttl("b", b"2".to_vec(), huge);
+        assert!(kv.write_batch(batch).is_err());
+
+        // 被拒绝的写入不会消耗序列号，也不会写入任何条目
+        assert_eq!(kv.stats().unwrap().sequence_number, 0);
+        assert!(kv.get("a").unwrap().is_none());
+        kv.set_with_ttl("k", b"v".to_vec(), MAX_TTL).unwrap();
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"v");
     }
 
     #[test]
@@ -555,11 +1937,346 @@ mod tests {
         kv.restore().unwrap();
 
         // 3. 验证状态
-        assert!(kv.get("k1".to_string()).unwrap().is_none()); // k1 应该是墓碑
-        let entry = kv.get("k2".to_string()).unwrap().unwrap();
+        assert!(kv.get("k1").unwrap().is_none()); // k1 应该是墓碑
+        let entry = kv.get("k2").unwrap().unwrap();
         assert_eq!(entry.value, b"v2");
     }
 
+    #[test]
+    fn test_binary_keys() {
+        let path = "test_binary_keys";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+
+        // 非 UTF-8 的 Key 以及包含 0 字节的 Key
+        let k1 = vec![0xff, 0xfe, 0x00, 0x01];
+        let k2 = vec![0x00];
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.set(k1.clone(), b"v1".to_vec()).unwrap();
+            kv.set(k2.clone(), b"v2".to_vec()).unwrap();
+            kv.remove(&k2).unwrap();
+            assert_eq!(kv.get(&k1).unwrap().unwrap().key, k1);
+        }
+
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        let entry = kv.get(&k1).unwrap().unwrap();
+        assert_eq!(entry.key, k1);
+        assert_eq!(entry.value, b"v1");
+        assert!(kv.get(&k2).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_scan() {
+        let path = "test_scan";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+
+        // 默认的 HashIndexer 不支持扫描
+        let kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        assert!(matches!(
+            kv.scan::<[u8], _>(..),
+            Err(TitaniumError::Unsupported(_))
+        ));
+        drop(kv);
+
+        let mut kv = KVStore::builder(watcher, fs)
+            .indexer_kind(IndexerKind::BTree)
+            .open()
+            .unwrap();
+        for key in ["user:2", "user:1", "order:1", "user:3", "user;1"] {
+            kv.set(key, key.as_bytes().to_vec()).unwrap();
+        }
+        kv.set(vec![b'u', 0xff], b"raw".to_vec()).unwrap();
+        kv.remove("user:2").unwrap();
+
+        let keys: Vec<&[u8]> = kv.scan_prefix("user:").unwrap().map(|e| e.key()).collect();
+        assert_eq!(keys, vec![b"user:1".as_slice(), b"user:3"]);
+
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = kv
+            .scan("order:1".."user:3")
+            .unwrap()
+            .map(|e| (e.key().to_vec(), e.value().unwrap().unwrap()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (b"order:1".to_vec(), b"order:1".to_vec()),
+                (b"user:1".to_vec(), b"user:1".to_vec()),
+            ]
+        );
+
+        // 前缀的最后一个字节为 0xff 时上界向前进位
+        let keys: Vec<&[u8]> = kv
+            .scan_prefix([b'u', 0xff])
+            .unwrap()
+            .map(|e| e.key())
+            .collect();
+        assert_eq!(keys, vec![[b'u', 0xff].as_slice()]);
+        assert_eq!(prefix_upper_bound(b"a\xff\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_upper_bound(b"\xff"), None);
+    }
+
+    #[test]
+    fn test_custom_indexer() {
+        use crate::index::BTreeIndexer;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // 统计 put 次数的索引器
+        struct CountingIndexer {
+            inner: BTreeIndexer,
+            puts: Arc<AtomicUsize>,
+        }
+
+        impl Indexer for CountingIndexer {
+            fn put(&mut self, key: Vec<u8>, index: LogIndex) {
+                self.puts.fetch_add(1, Ordering::Relaxed);
+                self.inner.put(key, index);
+            }
+            fn get(&self, key: &[u8]) -> Option<LogIndex> {
+                self.inner.get(key)
+            }
+            fn remove(&mut self, key: &[u8]) {
+                self.inner.remove(key);
+            }
+            fn iter(&self) -> IndexIter<'_> {
+                self.inner.iter()
+            }
+        }
+
+        let path = "test_custom_indexer";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.indexer = IndexerKind::BTree;
+        watcher.override_config(cfg);
+
+        // 配置中指定的索引类型
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.set("a", b"1".to_vec()).unwrap();
+            kv.set("b", b"2".to_vec()).unwrap();
+            assert_eq!(kv.scan_prefix("").unwrap().count(), 2);
+        }
+
+        // 自定义索引器优先于配置
+        let puts = Arc::new(AtomicUsize::new(0));
+        let mut kv = KVStore::builder(watcher, fs)
+            .indexer(Box::new(CountingIndexer {
+                inner: BTreeIndexer::new(),
+                puts: puts.clone(),
+            }))
+            .open()
+            .unwrap();
+        kv.restore().unwrap();
+        assert_eq!(puts.load(Ordering::Relaxed), 2);
+        assert_eq!(kv.get("b").unwrap().unwrap().value, b"2");
+        // 未实现 range 的索引器不支持扫描
+        assert!(matches!(
+            kv.scan_prefix(""),
+            Err(TitaniumError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_keys_and_iter() {
+        let path = "test_keys_and_iter";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.max_file_size = 50; // 强制轮转，使条目分布在多个文件中
+        cfg.indexer = IndexerKind::BTree;
+        watcher.override_config(cfg);
+
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        for i in 0..5 {
+            kv.set(format!("k{}", i), vec![i as u8; 10]).unwrap();
+        }
+        kv.remove("k1").unwrap();
+        kv.set_with_ttl("k5", b"tmp".to_vec(), Duration::from_millis(1))
+            .unwrap();
+        thread::sleep(Duration::from_millis(10));
+        assert!(kv.file_map.len() > 1);
+
+        let keys: Vec<&[u8]> = kv.keys().map(|k| k.unwrap()).collect();
+        assert_eq!(keys, vec![b"k0".as_slice(), b"k2", b"k3", b"k4"]);
+
+        let entries: Vec<LogEntry> = kv.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 4);
+        for entry in entries {
+            let i = entry.key[1] - b'0';
+            assert_eq!(entry.value, vec![i; 10]);
+        }
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let path = "test_write_batch";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+        let active_path = Path::new(path).join("0001.bs");
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.set("a", b"old".to_vec()).unwrap();
+            kv.set("b", b"old".to_vec()).unwrap();
+
+            let mut batch = WriteBatch::new();
+            batch
+                .put("a", b"new".to_vec())
+                .delete("b")
+                .put("c", b"new".to_vec());
+            kv.write_batch(batch).unwrap();
+            assert_eq!(kv.get("a").unwrap().unwrap().value, b"new");
+            assert!(kv.get("b").unwrap().is_none());
+        }
+
+        // 已提交的批次在恢复后完整生效
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.restore().unwrap();
+            assert_eq!(kv.get("a").unwrap().unwrap().value, b"new");
+            assert!(kv.get("b").unwrap().is_none());
+            assert_eq!(kv.get("c").unwrap().unwrap().value, b"new");
+
+            let mut batch = WriteBatch::new();
+            batch.put("a", b"lost".to_vec()).put("d", b"lost".to_vec());
+            kv.write_batch(batch).unwrap();
+        }
+
+        // 模拟崩溃：提交标记没有完整落盘
+        let file = fs.open_file(&active_path).unwrap();
+        let len = file.len().unwrap();
+        file.set_len(len - 1).unwrap();
+
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.get("a").unwrap().unwrap().value, b"new");
+        assert!(kv.get("d").unwrap().is_none());
+
+        // 恢复后继续写入，未完成的批次不会影响新数据
+        kv.set("e", b"v".to_vec()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("f", b"v".to_vec());
+        kv.write_batch(batch).unwrap();
+        let keys: Vec<&[u8]> = kv.keys().map(|k| k.unwrap()).collect();
+        assert_eq!(keys.len(), 4);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let path = "test_snapshot";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.max_file_size = 50; // 强制轮转，使旧版本进入归档文件
+        cfg.compaction_threshold = usize::MAX;
+        watcher.override_config(cfg);
+
+        let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        kv.set("a", b"a1".to_vec()).unwrap();
+        kv.set("b", b"b1".to_vec()).unwrap();
+        let snap = kv.snapshot();
+
+        kv.set("a", b"a2".to_vec()).unwrap();
+        kv.remove("b").unwrap();
+        kv.set("c", b"c1".to_vec()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("a", b"a3".to_vec()).put("b", b"b2".to_vec());
+        kv.write_batch(batch).unwrap();
+        kv.remove("b").unwrap();
+
+        let check = |kv: &KVStore| {
+            assert_eq!(kv.get_at(&snap, "a").unwrap().unwrap().value, b"a1");
+            assert_eq!(kv.get_at(&snap, "b").unwrap().unwrap().value, b"b1");
+            assert!(kv.get_at(&snap, "c").unwrap().is_none());
+            let entries: Vec<(Vec<u8>, Vec<u8>)> = kv
+                .iter_at(&snap)
+                .map(|e| e.map(|e| (e.key, e.value)).unwrap())
+                .collect();
+            assert_eq!(
+                entries,
+                vec![
+                    (b"a".to_vec(), b"a1".to_vec()),
+                    (b"b".to_vec(), b"b1".to_vec())
+                ]
+            );
+        };
+        check(&kv);
+        assert_eq!(kv.get("a").unwrap().unwrap().value, b"a3");
+        assert!(kv.get("b").unwrap().is_none());
+
+        // 合并必须保留快照需要的旧版本
+        kv.handle().unwrap().rotate().unwrap();
+        kv.compact().unwrap();
+        check(&kv);
+
+        // 释放快照后，下一次写入清理版本历史
+        drop(snap);
+        kv.set("d", b"d1".to_vec()).unwrap();
+        assert!(!kv.history.contains_key(b"a"));
+        drop(kv);
+
+        // 合并保留的旧版本不能在重启后复活
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.get("a").unwrap().unwrap().value, b"a3");
+        assert!(kv.get("b").unwrap().is_none());
+        assert_eq!(kv.get("c").unwrap().unwrap().value, b"c1");
+    }
+
+    #[test]
+    fn test_conditional_set() {
+        let (mut kv, _, _) = create_kv_store("test_conditional_set");
+
+        // NX / XX
+        assert!(!kv.set_if_present("k", b"v0".to_vec()).unwrap());
+        assert!(kv.set_if_absent("k", b"v1".to_vec()).unwrap());
+        assert!(!kv.set_if_absent("k", b"v2".to_vec()).unwrap());
+        assert!(kv.set_if_present("k", b"v2".to_vec()).unwrap());
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"v2");
+
+        // CAS
+        assert!(
+            !kv.compare_and_swap("k", Some(b"v1"), b"v3".to_vec())
+                .unwrap()
+        );
+        assert!(!kv.compare_and_swap("k", None, b"v3".to_vec()).unwrap());
+        assert!(
+            kv.compare_and_swap("k", Some(b"v2"), b"v3".to_vec())
+                .unwrap()
+        );
+        assert!(kv.compare_and_swap("new", None, b"n1".to_vec()).unwrap());
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"v3");
+
+        // 版本条件写入
+        let version = kv.get("k").unwrap().unwrap().sequence_number;
+        assert!(kv.set_if_version("k", version, b"v4".to_vec()).unwrap());
+        assert!(!kv.set_if_version("k", version, b"v5".to_vec()).unwrap());
+        assert!(!kv.set_if_version("missing", 0, b"v".to_vec()).unwrap());
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"v4");
+
+        // 已过期的 Key 视为不存在
+        kv.set_with_ttl("ttl", b"old".to_vec(), Duration::from_millis(1))
+            .unwrap();
+        thread::sleep(Duration::from_millis(10));
+        assert!(!kv.set_if_present("ttl", b"x".to_vec()).unwrap());
+        assert!(kv.set_if_absent("ttl", b"new".to_vec()).unwrap());
+    }
+
     #[test]
     fn test_restore_corrupted_data() {
         let path = "test_corrupt";
@@ -591,9 +2308,72 @@ mod tests {
 
         // 4. 验证
         // k1 应该还在 (因为它是先写入的，且未损坏)
-        assert!(kv.get("k1".to_string()).unwrap().is_some());
+        assert!(kv.get("k1").unwrap().is_some());
         // k2 应该丢失 (因为数据损坏被截断)
-        assert!(kv.get("k2".to_string()).unwrap().is_none());
+        assert!(kv.get("k2").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_only_open() {
+        let path = "test_read_only";
+        let (mut kv, fs, watcher) = create_kv_store(path);
+        kv.set("k1", b"v1".to_vec()).unwrap();
+        kv.set("k2", b"v2".to_vec()).unwrap();
+
+        // 读写实例持有独占锁，只读打开失败
+        assert!(matches!(
+            KVStore::builder(watcher.clone(), fs.clone())
+                .read_only()
+                .open(),
+            Err(TitaniumError::DirectoryLocked(_))
+        ));
+        drop(kv);
+
+        // 破坏最后一个条目，只读恢复时忽略损坏的尾部但不修改文件
+        let file_path = Path::new(path).join("0001.bs");
+        let mut file = fs.open_file(&file_path).unwrap();
+        file.seek(io::SeekFrom::End(-20)).unwrap();
+        file.write_all(&[0xFF, 0xFF, 0xFF]).unwrap();
+        let len = file.len().unwrap();
+        let files = fs.list_files(Path::new(path)).unwrap().len();
+
+        let mut kv = KVStore::builder(watcher.clone(), fs.clone())
+            .read_only()
+            .open()
+            .unwrap();
+        kv.restore().unwrap();
+        assert!(kv.is_read_only());
+        assert_eq!(kv.get("k1").unwrap().unwrap().value, b"v1");
+        assert!(kv.get("k2").unwrap().is_none());
+        assert_eq!(fs.open_reader(&file_path).unwrap().len().unwrap(), len);
+        // 没有创建新的活跃文件
+        assert_eq!(fs.list_files(Path::new(path)).unwrap().len(), files);
+
+        // 所有写操作都被拒绝
+        assert!(matches!(
+            kv.set("k3", b"v3".to_vec()),
+            Err(TitaniumError::ReadOnly)
+        ));
+        assert!(matches!(kv.remove("k1"), Err(TitaniumError::ReadOnly)));
+        assert!(matches!(
+            kv.set_if_absent("k3", b"v3".to_vec()),
+            Err(TitaniumError::ReadOnly)
+        ));
+        assert!(matches!(kv.compact(), Err(TitaniumError::ReadOnly)));
+        kv.sync().unwrap();
+
+        // 多个只读实例可以共存，但与读写实例互斥
+        let other = KVStore::builder(watcher.clone(), fs.clone())
+            .read_only()
+            .open()
+            .unwrap();
+        assert!(matches!(
+            KVStore::new(watcher.clone(), fs.clone()),
+            Err(TitaniumError::DirectoryLocked(_))
+        ));
+        drop(other);
+        drop(kv);
+        KVStore::new(watcher, fs).unwrap();
     }
 
     #[test]
@@ -621,8 +2401,8 @@ mod tests {
         let mut kv = KVStore::new(watcher, fs).unwrap();
         kv.restore().unwrap();
 
-        assert!(kv.get("k1".to_string()).unwrap().is_some());
-        assert!(kv.get("k2".to_string()).unwrap().is_none());
+        assert!(kv.get("k1").unwrap().is_some());
+        assert!(kv.get("k2").unwrap().is_none());
     }
 
     #[test]
@@ -652,9 +2432,253 @@ mod tests {
         assert!(fs.exists(&Path::new(path).join("0002.bs")));
 
         // 验证所有数据可读
-        assert!(kv.get("k1".to_string()).unwrap().is_some());
-        assert!(kv.get("k2".to_string()).unwrap().is_some());
-        assert!(kv.get("k3".to_string()).unwrap().is_some());
-        assert!(kv.get("k4".to_string()).unwrap().is_some());
+        assert!(kv.get("k1").unwrap().is_some());
+        assert!(kv.get("k2").unwrap().is_some());
+        assert!(kv.get("k3").unwrap().is_some());
+        assert!(kv.get("k4").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_remove_rotates() {
+        let (mut kv, fs, watcher) = create_kv_store("test_remove_rotates");
+        for i in 0..20 {
+            kv.set(format!("k{}", i), vec![0u8; 10]).unwrap();
+        }
+        let mut cfg = watcher.get();
+        cfg.max_file_size = 50;
+        cfg.compaction_threshold = usize::MAX;
+        cfg.min_free_space = 1024;
+        watcher.override_config(cfg);
+
+        // 只有删除的负载也会轮转，产生可供合并的归档文件
+        for i in 0..10 {
+            kv.remove(format!("k{}", i)).unwrap();
+        }
+        assert!(kv.stats().unwrap().pending_compaction_files >= 4);
+        assert!(kv.active.as_ref().unwrap().writer.current_offset() < 100);
+
+        // 空间不足无法轮转时，删除继续写入当前文件
+        fs.set_capacity(512);
+        for i in 10..20 {
+            kv.remove(format!("k{}", i)).unwrap();
+        }
+        assert!(kv.keys().next().is_none());
+    }
+
+    #[test]
+    fn test_restore_from_hint() {
+        let path = "test_restore_hint";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.max_file_size = 50;
+        cfg.compaction_threshold = usize::MAX;
+        watcher.override_config(cfg);
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.set("k1".to_string(), vec![1u8; 10]).unwrap();
+            kv.set("k2".to_string(), vec![2u8; 10]).unwrap();
+            kv.remove("k1").unwrap();
+            kv.set("k3".to_string(), vec![3u8; 10]).unwrap();
+            kv.set("k4".to_string(), vec![4u8; 10]).unwrap();
+        }
+
+        // 封存的文件都应该生成了 Hint 文件
+        assert!(fs.exists(&hint::hint_path(Path::new(path), 1)));
+
+        let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        kv.restore().unwrap();
+        assert!(kv.get("k1").unwrap().is_none());
+        assert_eq!(kv.get("k2").unwrap().unwrap().value, vec![2u8; 10]);
+        assert_eq!(kv.get("k4").unwrap().unwrap().value, vec![4u8; 10]);
+        assert!(kv.current_seq_no >= 5);
+
+        // Hint 文件失效时回退到全量扫描，结果一致
+        let file = fs.open_file(&hint::hint_path(Path::new(path), 1)).unwrap();
+        file.set_len(6).unwrap();
+        drop(kv);
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        assert!(kv.get("k1").unwrap().is_none());
+        assert_eq!(kv.get("k2").unwrap().unwrap().value, vec![2u8; 10]);
+    }
+
+    #[test]
+    fn test_write_backpressure() {
+        let path = "test_backpressure";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.max_file_size = 30;
+        cfg.compaction_threshold = usize::MAX;
+        cfg.write_stall_threshold = 1;
+        cfg.write_stop_threshold = 3;
+        watcher.override_config(cfg);
+
+        let mut kv = KVStore::new(watcher, fs.clone()).unwrap();
+        // 每次写入约 30 字节，几乎每次都会轮转
+        for i in 0..10 {
+            kv.set(format!("k{}", i), vec![0u8; 10]).unwrap();
+            // 达到 stop 阈值时会同步合并，待合并文件数不会超过阈值
+            assert!(kv.pending_files <= 3);
+        }
+        for i in 0..10 {
+            assert!(kv.get(format!("k{}", i)).unwrap().is_some());
+        }
+
+        // 合并失败时，超过 stop 阈值的写入被拒绝
+        while kv.pending_files < 3 {
+            kv.set("filler".to_string(), vec![0u8; 10]).unwrap();
+        }
+        let first_archived = *kv.file_map.keys().min().unwrap();
+        let file = fs
+            .open_file(&Path::new(path).join(format!("{:04}.bs", first_archived)))
+            .unwrap();
+        file.set_len(FILE_HEADER_SIZE + 5).unwrap();
+
+        match kv.set("k_overload".to_string(), vec![0u8; 10]) {
+            Err(TitaniumError::SystemOverload) => (),
+            other => panic!("Expected SystemOverload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pending_files_after_reopen() {
+        let path = "test_pending_reopen";
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        cfg.max_file_size = 30;
+        cfg.compaction_threshold = usize::MAX;
+        cfg.write_stall_threshold = 2;
+        cfg.write_stop_threshold = usize::MAX;
+        watcher.override_config(cfg);
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            for i in 0..10 {
+                kv.set(format!("k{}", i), vec![0u8; 10]).unwrap();
+            }
+            kv.compact().unwrap();
+            assert!(kv.file_map.len() >= 2);
+            assert_eq!(kv.pending_files, 0);
+        }
+
+        // 合并过的文件不再计入，只有关闭时的活跃文件是新封存的归档文件
+        let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.stats().unwrap().pending_compaction_files, 1);
+        assert!(kv.stall_delay().is_none());
+        kv.set("k10".to_string(), vec![0u8; 10]).unwrap();
+        for i in 0..=10 {
+            assert!(kv.get(format!("k{}", i)).unwrap().is_some());
+        }
+        drop(kv);
+
+        // 从未合并过的目录：所有归档文件都待合并
+        fs.remove_file(&Path::new(path).join(crate::compaction::MERGE_PROGRESS))
+            .unwrap();
+        let kv = KVStore::new(watcher, fs).unwrap();
+        assert_eq!(kv.pending_files, kv.file_map.len());
+        assert!(kv.stall_delay().is_some());
+    }
+
+    #[test]
+    fn test_disk_full() {
+        let (mut kv, fs, watcher) = create_kv_store("test_disk_full");
+        let mut cfg = watcher.get();
+        cfg.min_free_space = 1024;
+        watcher.override_config(cfg);
+
+        fs.set_capacity(512);
+        match kv.set("k1".to_string(), b"v1".to_vec()) {
+            Err(TitaniumError::DiskFull {
+                available,
+                required,
+            }) => {
+                assert!(available <= 512);
+                assert_eq!(required, 1024);
+            }
+            other => panic!("Expected DiskFull, got {:?}", other),
+        }
+        assert!(kv.get("k1").unwrap().is_none());
+
+        // 空间恢复后写入继续
+        fs.set_capacity(1024 * 1024);
+        kv.set("k1".to_string(), b"v1".to_vec()).unwrap();
+        assert!(kv.get("k1").unwrap().is_some());
+
+        // 删除不受空间限制，但写入的墓碑计入下一次检查前的字节数
+        let before = kv.active.as_ref().unwrap().bytes_since_space_check;
+        fs.set_capacity(512);
+        kv.remove("k1").unwrap();
+        assert!(kv.active.as_ref().unwrap().bytes_since_space_check > before);
+    }
+
+    #[test]
+    fn test_file_header_versions() {
+        let path = "test_file_header";
+        let dir = Path::new(path);
+        let fs = Arc::new(MemFileSystem::new());
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+
+        // 0001.bs: 没有文件头的旧格式文件
+        {
+            let mut file = fs.create_file(&dir.join("0001.bs")).unwrap();
+            let entry = LogEntry::new("legacy".to_string(), b"v0".to_vec(), 1).build();
+            entry.encode_to(&mut file).unwrap();
+        }
+        // 0002.bs: 外部文件
+        {
+            let mut file = fs.create_file(&dir.join("0002.bs")).unwrap();
+            file.write_all(b"this is not a titanium data file").unwrap();
+        }
+
+        {
+            let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+            kv.restore().unwrap();
+            assert_eq!(kv.get("legacy").unwrap().unwrap().value, b"v0");
+            // 旧格式文件不会被复用为活跃文件，外部文件被跳过且不会被覆盖
+            assert_eq!(kv.active.as_ref().unwrap().id, 3);
+            assert!(!kv.file_map.contains_key(&2));
+            kv.set("new".to_string(), b"v1".to_vec()).unwrap();
+        }
+
+        // 新文件以文件头开始
+        let file = fs.open_reader(&dir.join("0003.bs")).unwrap();
+        let mut buf = [0u8; FILE_HEADER_SIZE as usize];
+        file.read_at(&mut buf, 0).unwrap();
+        assert_eq!(
+            FileHeader::decode(&buf).unwrap().version,
+            CURRENT_FORMAT_VERSION
+        );
+
+        let mut kv = KVStore::new(watcher.clone(), fs.clone()).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.get("legacy").unwrap().unwrap().value, b"v0");
+        assert_eq!(kv.get("new").unwrap().unwrap().value, b"v1");
+        drop(kv);
+
+        // 版本比当前程序新的数据文件：拒绝打开
+        {
+            let mut file = fs.create_file(&dir.join("0004.bs")).unwrap();
+            FileHeader {
+                version: CURRENT_FORMAT_VERSION + 1,
+            }
+            .encode_to(&mut file)
+            .unwrap();
+        }
+        match KVStore::new(watcher, fs) {
+            Err(TitaniumError::UnsupportedVersion(v)) => assert_eq!(v, CURRENT_FORMAT_VERSION + 1),
+            Err(e) => panic!("Expected UnsupportedVersion, got {:?}", e),
+            Ok(_) => panic!("Expected UnsupportedVersion, got Ok"),
+        }
     }
 }
@@ -0,0 +1,17 @@
+pub mod batch;
+pub mod compaction;
+pub mod config;
+pub mod error;
+pub mod hint;
+pub mod index;
+pub mod kv;
+pub mod log_entry;
+pub mod server;
+pub mod shared;
+pub mod snapshot;
+pub mod storage;
+pub mod syncer;
+pub mod transaction;
+pub mod upgrade;
+pub mod utils;
+pub mod writer;
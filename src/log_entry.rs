@@ -6,6 +6,61 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use std::io::{self, Read, Write};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// 数据文件头魔数
+pub const FILE_MAGIC: &[u8; 4] = b"TITN";
+/// 当前写入的数据文件格式版本
+///
+/// - 1: 引入文件头
+/// - 2: 条目类型增加 WriteBatch 标志位 (BATCH / COMMIT)，旧程序无法正确理解，因此提升版本
+pub const CURRENT_FORMAT_VERSION: u8 = 2;
+/// 早期没有文件头的数据文件，条目编码与版本 1 相同
+pub const LEGACY_FORMAT_VERSION: u8 = 0;
+/// 文件头长度：Magic(4B) + Version(1B) + Reserved(3B)
+pub const FILE_HEADER_SIZE: u64 = 8;
+
+/// 数据文件头，位于每个 .bs 文件的开头
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u8,
+}
+
+impl FileHeader {
+    pub fn current() -> Self {
+        Self {
+            version: CURRENT_FORMAT_VERSION,
+        }
+    }
+
+    pub fn encode_to<W: Write>(&self, writer: &mut W) -> Result<(), TitaniumError> {
+        let mut buf = [0u8; FILE_HEADER_SIZE as usize];
+        buf[..4].copy_from_slice(FILE_MAGIC);
+        buf[4] = self.version;
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// 解析文件头，魔数不匹配时返回 None
+    pub fn decode(buf: &[u8; FILE_HEADER_SIZE as usize]) -> Option<Self> {
+        if &buf[..4] != FILE_MAGIC {
+            return None;
+        }
+        Some(Self { version: buf[4] })
+    }
+
+    /// 该版本的数据文件中，第一个条目的起始偏移
+    pub fn data_offset(version: u8) -> u64 {
+        if version == LEGACY_FORMAT_VERSION {
+            0
+        } else {
+            FILE_HEADER_SIZE
+        }
+    }
+
+    pub fn is_supported(version: u8) -> bool {
+        version <= CURRENT_FORMAT_VERSION
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct EntryType(pub u8);
 
@@ -14,18 +69,24 @@ impl EntryType {
     /// 表示该条目是一个删除操作。
     const TOMBSTONE: u8 = 1 << 0;
 
-    /// Bit 1: 预留 (Reserved)
-    /// 暂未使用。
+    /// Bit 1: 批次标记 (Batch)
+    /// 表示该条目属于一个 WriteBatch，只有读到对应的提交标记后才生效。
+    const BATCH: u8 = 1 << 1;
 
     /// Bit 2: 是否包含 TTL (Time To Live)
     /// 表示 Header 中是否包含过期时间戳 (expire_at)。
     /// 如果该位未设置，则表示没有过期时间，Header 中也不会写入 expire_at 字段，以节省空间。
     const TTL: u8 = 1 << 2;
 
-    /// Bit 3-7: 预留 (Reserved)
+    /// Bit 3: 批次提交标记 (Commit)
+    /// 与 BATCH 同时设置，表示一个 WriteBatch 的结束。Key 为 varint 编码的批次条目数，Value 为空。
+    const COMMIT: u8 = 1 << 3;
+
+    // Bit 4-7: 预留 (Reserved)
 
     const NORMAL: Self = Self(0);
     const DELETE: Self = Self(Self::TOMBSTONE);
+    const BATCH_COMMIT: Self = Self(Self::BATCH | Self::COMMIT);
 
     pub fn new(val: u8) -> Self {
         Self(val)
@@ -39,6 +100,14 @@ impl EntryType {
         self.0 & Self::TTL != 0
     }
 
+    pub fn is_batch(&self) -> bool {
+        self.0 & Self::BATCH != 0
+    }
+
+    pub fn is_batch_commit(&self) -> bool {
+        self.0 & Self::COMMIT != 0
+    }
+
     /// 在 Encode 阶段标记 TTL 位 (设置 Bit 2)
     pub fn mark_ttl(&mut self) {
         self.0 |= Self::TTL;
@@ -49,7 +118,8 @@ impl EntryType {
 #[derive(Debug, PartialEq)]
 pub struct LogEntry {
     entry_type: EntryType,
-    pub key: String,
+    /// Key 是任意字节序列 (可以是 UTF-8 字符串，也可以是编码后的元组、UUID 等二进制数据)
+    pub key: Vec<u8>,
     pub value: Vec<u8>,
     pub sequence_number: u64,
     pub created_at: u64,
@@ -79,7 +149,7 @@ impl LogEntryBuilder {
 #[derive(Debug, PartialEq)]
 pub struct LogHeader {
     entry_type: EntryType,
-    pub key: String,
+    pub key: Vec<u8>,
     pub val_len: u32,
     pub created_at: u64,
     pub expire_at: Option<u64>,
@@ -90,6 +160,20 @@ impl LogHeader {
     pub fn is_tombstone(&self) -> bool {
         self.entry_type.is_tombstone()
     }
+
+    /// 是否属于一个 WriteBatch (不包括提交标记本身)
+    pub fn is_batch(&self) -> bool {
+        self.entry_type.is_batch() && !self.entry_type.is_batch_commit()
+    }
+
+    pub fn is_batch_commit(&self) -> bool {
+        self.entry_type.is_batch_commit()
+    }
+
+    /// 提交标记中记录的批次条目数，Key 无法解析时返回 None
+    pub fn batch_count(&self) -> Option<u32> {
+        decode_varint(&mut self.key.as_slice()).ok()
+    }
 }
 
 // zero allocation decoder
@@ -98,12 +182,16 @@ pub struct Decoder {
     value_buf: Vec<u8>,
     max_key_size: usize,
     max_val_size: usize,
+    // 待解码数据文件的格式版本，决定使用哪种条目布局
+    version: u8,
 }
 
 impl LogEntry {
     /// 工厂方法：创建普通日志条目
     /// 返回一个 Builder，用于进一步配置可选参数 (如 TTL)
-    pub fn new(key: String, value: Vec<u8>, sequence_number: u64) -> LogEntryBuilder {
+    /// `key` 接受 `String`、`&str`、`Vec<u8>`、`&[u8]` 等任何可以转换为字节的类型
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(key: impl Into<Vec<u8>>, value: Vec<u8>, sequence_number: u64) -> LogEntryBuilder {
         let created_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -111,7 +199,7 @@ impl LogEntry {
         LogEntryBuilder {
             entry: Self {
                 entry_type: EntryType::NORMAL,
-                key,
+                key: key.into(),
                 value,
                 sequence_number,
                 created_at,
@@ -121,14 +209,34 @@ impl LogEntry {
     }
 
     /// 工厂方法：创建删除标记 (墓碑)
-    pub fn new_tombstone(key: String, sequence_number: u64) -> Self {
+    pub fn new_tombstone(key: impl Into<Vec<u8>>, sequence_number: u64) -> Self {
         let created_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis() as u64;
         Self {
             entry_type: EntryType::DELETE,
-            key,
+            key: key.into(),
+            value: Vec::new(),
+            sequence_number,
+            created_at,
+            expire_at: None,
+        }
+    }
+
+    /// 工厂方法：创建 WriteBatch 的提交标记
+    ///
+    /// `sequence_number` 与批次中最后一个条目相同，恢复时据此校验批次的完整性。
+    pub fn new_batch_commit(count: u32, sequence_number: u64) -> Self {
+        let mut buf = [0u8; 5];
+        let n = encode_varint(count, &mut buf);
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        Self {
+            entry_type: EntryType::BATCH_COMMIT,
+            key: buf[..n].to_vec(),
             value: Vec::new(),
             sequence_number,
             created_at,
@@ -140,6 +248,25 @@ impl LogEntry {
         self.expire_at
     }
 
+    pub fn is_tombstone(&self) -> bool {
+        self.entry_type.is_tombstone()
+    }
+
+    /// 将条目标记为 WriteBatch 的一部分
+    pub fn mark_batch(&mut self) {
+        self.entry_type.0 |= EntryType::BATCH;
+    }
+
+    /// 清除批次标记：批次提交后，合并等重写流程将其作为独立条目写出
+    pub fn clear_batch(&mut self) {
+        self.entry_type.0 &= !EntryType::BATCH;
+    }
+
+    /// 便捷方法：以 UTF-8 字符串形式查看 Key，二进制 Key 返回 None
+    pub fn key_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.key).ok()
+    }
+
     fn encode_header<W: Write>(&self, writer: &mut W) -> Result<u64, TitaniumError> {
         // 1. 准备栈上缓冲区 (Stack Allocation)
         // 最大元数据长度：Type(1) + CreatedAt(10) + SeqNo(10) + KLen(5) + VLen(5) + ExpireAt(10) = 41 bytes
@@ -170,13 +297,13 @@ impl LogEntry {
         // 2. 计算 Header CRC
         let mut hasher = crc32fast::Hasher::new();
         hasher.update(&buf[..offset]);
-        hasher.update(self.key.as_bytes());
+        hasher.update(&self.key);
         let header_crc = hasher.finalize();
 
         // 3. 写入 Header
         writer.write_all(&header_crc.to_le_bytes())?;
         writer.write_all(&buf[..offset])?;
-        writer.write_all(self.key.as_bytes())?;
+        writer.write_all(&self.key)?;
 
         // 返回 Header 总长度
         Ok((4 + offset + self.key.len()) as u64)
@@ -206,9 +333,19 @@ impl Decoder {
             value_buf: Vec::new(),
             max_key_size,
             max_val_size,
+            version: CURRENT_FORMAT_VERSION,
         }
     }
 
+    /// 切换到指定格式版本的解码逻辑
+    pub fn set_version(&mut self, version: u8) {
+        self.version = version;
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub fn set_limits(&mut self, max_key_size: usize, max_val_size: usize) {
         self.max_key_size = max_key_size;
         self.max_val_size = max_val_size;
@@ -253,9 +390,22 @@ impl Decoder {
     /// 解码头部、Key 和 Value 以进行 CRC 校验，但仅返回头部信息 (CRC, Key, Value长度)。
     /// 优化：此方法现在只读取 Key 并验证 Header CRC，**不读取** Value 和 BodyCRC。
     /// 调用者需要负责跳过 BodyCRC 和 Value (seek 4 + val_len)。
+    ///
+    /// 根据 `set_version` 设置的格式版本选择解码逻辑。
     pub fn decode_header_and_key<R: Read>(
         &mut self,
         reader: &mut R,
+    ) -> Result<Option<LogHeader>, TitaniumError> {
+        match self.version {
+            // 版本 0 (无文件头)、1、2 的条目布局相同，版本 2 只是增加了标志位
+            LEGACY_FORMAT_VERSION | 1 | 2 => self.decode_header_and_key_v1(reader),
+            v => Err(TitaniumError::UnsupportedVersion(v)),
+        }
+    }
+
+    fn decode_header_and_key_v1<R: Read>(
+        &mut self,
+        reader: &mut R,
     ) -> Result<Option<LogHeader>, TitaniumError> {
         let mut header_crc_buf = [0u8; 4];
         // 尝试读取第一个字节来判断 EOF
@@ -320,14 +470,11 @@ impl Decoder {
 
         // 3. 停止读取！
         // 我们不读取 BodyCRC 和 Value，也不进行 Body CRC 校验。
-        let key = String::from_utf8(self.key_buf.clone())
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Key is not valid UTF-8"))?;
-
         Ok(Some(LogHeader {
             entry_type,
             created_at,
             sequence_number,
-            key,
+            key: self.key_buf.clone(),
             val_len: v_len,
             expire_at,
         }))
@@ -379,7 +526,7 @@ mod tests {
         let entry = decoder.decode_from(&mut cursor).unwrap().unwrap();
 
         assert_eq!(entry.entry_type, EntryType::NORMAL);
-        assert_eq!(entry.key, key);
+        assert_eq!(entry.key, key.as_bytes());
         assert_eq!(entry.value, value);
     }
 
@@ -415,14 +562,13 @@ mod tests {
 
         let mut cursor = Cursor::new(buf);
         let mut decoder = Decoder::new(1024, 1024 * 1024);
-        let log_header;
-        match decoder.decode_header_and_key(&mut cursor)? {
-            Some(header) => log_header = header,
+        let log_header = match decoder.decode_header_and_key(&mut cursor)? {
+            Some(header) => header,
             None => panic!("Unexpected EOF"),
         };
 
         assert_eq!(log_header.entry_type, EntryType::NORMAL);
-        assert_eq!(log_header.key, key);
+        assert_eq!(log_header.key, key.as_bytes());
         assert_eq!(log_header.val_len, 100);
 
         // Verify cursor position (should be at the END of value, because we consumed it for CRC check)
@@ -456,7 +602,7 @@ mod tests {
 
         assert!(entry.entry_type.has_ttl());
         assert_eq!(entry.expire_at, Some(expire_at));
-        assert_eq!(entry.key, key);
+        assert_eq!(entry.key, key.as_bytes());
         assert_eq!(entry.value, value);
     }
 
@@ -529,4 +675,49 @@ mod tests {
             _ => panic!("Expected UnexpectedEof in value, got {:?}", err),
         }
     }
+
+    #[test]
+    fn test_file_header() {
+        let mut buf = Vec::new();
+        FileHeader::current().encode_to(&mut buf).unwrap();
+        assert_eq!(buf.len() as u64, FILE_HEADER_SIZE);
+        assert_eq!(&buf[..4], FILE_MAGIC);
+
+        let header = FileHeader::decode(buf.as_slice().try_into().unwrap()).unwrap();
+        assert_eq!(header.version, CURRENT_FORMAT_VERSION);
+        assert_eq!(FileHeader::data_offset(header.version), FILE_HEADER_SIZE);
+        assert_eq!(FileHeader::data_offset(LEGACY_FORMAT_VERSION), 0);
+
+        buf[0] = b'X';
+        assert!(FileHeader::decode(buf.as_slice().try_into().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut buf = Vec::new();
+        let entry = LogEntry::new("key".to_string(), b"val".to_vec(), 1).build();
+        entry.encode_to(&mut buf).unwrap();
+
+        let mut decoder = Decoder::new(1024, 1024);
+        decoder.set_version(CURRENT_FORMAT_VERSION + 1);
+        let err = decoder.decode_from(&mut Cursor::new(buf)).unwrap_err();
+        match err {
+            TitaniumError::UnsupportedVersion(v) => assert_eq!(v, CURRENT_FORMAT_VERSION + 1),
+            _ => panic!("Expected UnsupportedVersion, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_binary_key() {
+        // 非 UTF-8 的二进制 Key (例如 UUID 字节或编码后的元组)
+        let key = vec![0x00, 0xFF, 0xC3, 0x28, 0x80];
+        let mut buf = Vec::new();
+        let entry = LogEntry::new(key.clone(), b"val".to_vec(), 1).build();
+        entry.encode_to(&mut buf).unwrap();
+
+        let mut decoder = Decoder::new(1024, 1024);
+        let entry = decoder.decode_from(&mut Cursor::new(buf)).unwrap().unwrap();
+        assert_eq!(entry.key, key);
+        assert!(entry.key_str().is_none());
+    }
 }
@@ -1,96 +1,138 @@
-mod compaction;
-mod config;
-mod error;
-mod index;
-mod kv;
-mod log_entry;
-mod storage;
-mod utils;
-mod writer;
+mod cli;
+mod repl;
 
-use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
+#[cfg(unix)]
+use std::thread;
 
-use crate::config::{ConfigWatcher, DEFAULT_CONFIG_FILE};
-use crate::error::TitaniumError;
-use crate::kv::KVStore;
-use crate::storage::OsFileSystem;
+use cli::Args;
+use repl::Repl;
+use titanium_engine::config::ConfigWatcher;
+use titanium_engine::error::TitaniumError;
+use titanium_engine::kv::KVStore;
+#[cfg(unix)]
+use titanium_engine::server::bind_unix;
+use titanium_engine::server::{HttpServer, Server};
+use titanium_engine::storage::OsFileSystem;
+use titanium_engine::upgrade::Upgrader;
 
-fn main() -> Result<(), TitaniumError> {
-    ConfigWatcher::init(DEFAULT_CONFIG_FILE)?;
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            return ExitCode::from(cli::EXIT_USAGE);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    if let Err(e) = cli::init_config(&args) {
+        eprintln!("Error: {}", e);
+        return ExitCode::from(cli::EXIT_USAGE);
+    }
+
+    // upgrade 和 serve 不经过 cli::run，在这里检查多余的参数和选项
+    if let Some("upgrade" | "serve" | "serve-http") = args.command.as_deref()
+        && let Err(code) = cli::expect_args(&args, 0, 1)
+    {
+        return code;
+    }
+    let positional = args.positional.first().map(String::as_str);
+    let result = match args.command.as_deref() {
+        None => run_repl(),
+        Some("upgrade") => run_upgrade(positional),
+        Some("serve") => run_server(positional),
+        Some("serve-http") => run_http_server(positional),
+        Some(_) => return cli::run(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(cli::EXIT_ERROR)
+        }
+    }
+}
+
+/// 交互式命令行
+fn run_repl() -> Result<(), TitaniumError> {
     let watcher = ConfigWatcher::global().clone();
-    let fs = Arc::new(OsFileSystem);
-    let mut kv_store = KVStore::new(watcher, fs)?;
+    let mut kv_store = KVStore::new(watcher, Arc::new(OsFileSystem))?;
     // restore or initialize the KV store as needed
     kv_store.restore()?;
+    Repl::new(kv_store).run()
+}
 
-    println!("Welcome to Titanium KV Store!");
-    println!("Commands: SET <key> <value> | GET <key> | RM <key> | EXIT");
-
-    let mut input = String::new();
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
-        input.clear();
-
-        match io::stdin().read_line(&mut input) {
-            Ok(0) => break, // EOF
-            Ok(_) => {
-                let trimmed = input.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
-
-                let mut parts = trimmed.split_whitespace();
-                let command = parts.next().unwrap_or("").to_uppercase();
+/// 启动 RESP 服务，监听配置中的 server_addr (可由参数覆盖) 和 unix_socket，二者至少配置一个
+fn run_server(addr: Option<&str>) -> Result<(), TitaniumError> {
+    let watcher = ConfigWatcher::global().clone();
+    let config = watcher.get();
+    let addr = addr.map_or(config.server_addr, str::to_string);
+    if addr.is_empty() && config.unix_socket.is_none() {
+        return Err(TitaniumError::ConfigError(
+            "No listener configured: set server_addr or unix_socket".to_string(),
+        ));
+    }
+    let mut kv_store = KVStore::new(watcher, Arc::new(OsFileSystem))?;
+    kv_store.restore()?;
+    let server = Server::new(kv_store.into_shared()?);
 
-                match command.as_str() {
-                    "SET" => {
-                        if let Some(key) = parts.next() {
-                            let value_parts: Vec<&str> = parts.collect();
-                            if value_parts.is_empty() {
-                                println!("Usage: SET <key> <value>");
-                            } else {
-                                let value = value_parts.join(" ");
-                                match kv_store.set(key.to_string(), value.into_bytes()) {
-                                    Ok(_) => println!("OK"),
-                                    Err(e) => eprintln!("Error: {}", e),
-                                }
-                            }
-                        } else {
-                            println!("Usage: SET <key> <value>");
-                        }
-                    }
-                    "GET" => {
-                        if let Some(key) = parts.next() {
-                            match kv_store.get(key.to_string()) {
-                                Ok(Some(entry)) => match String::from_utf8(entry.value) {
-                                    Ok(s) => println!("{}", s),
-                                    Err(e) => println!("{:?}", e.into_bytes()),
-                                },
-                                Ok(None) => println!("(nil)"),
-                                Err(e) => eprintln!("Error: {}", e),
-                            }
-                        } else {
-                            println!("Usage: GET <key>");
-                        }
-                    }
-                    "RM" => {
-                        if let Some(key) = parts.next() {
-                            match kv_store.remove(key) {
-                                Ok(_) => println!("OK"),
-                                Err(e) => eprintln!("Error: {}", e),
-                            }
-                        } else {
-                            println!("Usage: RM <key>");
-                        }
-                    }
-                    "EXIT" => break,
-                    _ => println!("Unknown command: {}", command),
-                }
+    if let Some(path) = config.unix_socket {
+        #[cfg(unix)]
+        {
+            let listener = bind_unix(&path, config.unix_socket_perm)?;
+            println!("Listening on unix:{}", path);
+            if addr.is_empty() {
+                return server.serve_unix(listener);
+            }
+            let server = server.clone();
+            thread::spawn(move || server.serve_unix(listener));
+        }
+        #[cfg(not(unix))]
+        {
+            eprintln!(
+                "unix_socket {} ignored: not supported on this platform",
+                path
+            );
+            if addr.is_empty() {
+                return Ok(());
             }
-            Err(e) => return Err(TitaniumError::Io(e)),
         }
     }
+    server.serve_tcp(addr)
+}
+
+/// 启动 HTTP/JSON 服务，监听地址默认取配置中的 http_addr
+fn run_http_server(addr: Option<&str>) -> Result<(), TitaniumError> {
+    let watcher = ConfigWatcher::global().clone();
+    let addr = addr.map_or_else(|| watcher.get().http_addr, str::to_string);
+    let mut kv_store = KVStore::new(watcher, Arc::new(OsFileSystem))?;
+    kv_store.restore()?;
+    HttpServer::new(kv_store.into_shared()?).serve_tcp(addr)
+}
+
+/// 离线升级数据目录中的旧格式文件，运行前必须停止所有使用该目录的进程
+fn run_upgrade(data_dir: Option<&str>) -> Result<(), TitaniumError> {
+    let config = ConfigWatcher::current();
+    let data_dir = data_dir.unwrap_or(&config.data_dir);
+    println!("Upgrading data directory {}...", data_dir);
+
+    let report = Upgrader::upgrade(
+        &OsFileSystem,
+        Path::new(data_dir),
+        config.max_key_size,
+        config.max_val_size,
+    )?;
+    println!(
+        "Done: {} files upgraded ({} entries), {} already up to date, {} foreign files skipped.",
+        report.upgraded.len(),
+        report.entries,
+        report.up_to_date.len(),
+        report.foreign.len()
+    );
     Ok(())
 }
@@ -0,0 +1,524 @@
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use titanium_engine::error::TitaniumError;
+use titanium_engine::kv::KVStore;
+use titanium_engine::utils::{base64_decode, base64_encode, glob_match, hex_decode, hex_encode};
+
+const HELP: &str = "\
+Commands:
+  SET <key> <value> | GET <key> | RM <key> | EXISTS <key> [key ...] | KEYS <pattern>
+  SETEX <key> <seconds> <value> | TTL <key>
+  SETNX <key> <value> | SETXX <key> <value> | CAS <key> <expected|(nil)> <new>
+  VERSION <key> | SETVER <key> <version> <value>
+  MODE [text|hex|base64] | HELP | EXIT
+Arguments are split on spaces; use \"...\" (escapes: \\n \\r \\t \\\\ \\\" \\xNN) or '...' for others.
+In hex/base64 mode, values are entered and printed in that encoding; keys are always text.";
+
+/// Value 的输入输出编码
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// 输出为带引号的字符串，不可打印字节转义为 `\xNN`
+    Text,
+    Hex,
+    Base64,
+}
+
+impl Mode {
+    fn decode(self, arg: Vec<u8>) -> Result<Vec<u8>, ReplError> {
+        let decode = match self {
+            Mode::Text => return Ok(arg),
+            Mode::Hex => hex_decode,
+            Mode::Base64 => base64_decode,
+        };
+        std::str::from_utf8(&arg)
+            .ok()
+            .and_then(decode)
+            .ok_or_else(|| ReplError::Invalid(format!("Invalid {} value", self.name())))
+    }
+
+    fn encode(self, value: &[u8]) -> String {
+        match self {
+            Mode::Text => quote(value),
+            Mode::Hex => hex_encode(value),
+            Mode::Base64 => base64_encode(value),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Text => "text",
+            Mode::Hex => "hex",
+            Mode::Base64 => "base64",
+        }
+    }
+}
+
+enum Outcome {
+    Print(String),
+    Continue,
+    Exit,
+}
+
+enum ReplError {
+    Usage(&'static str),
+    /// 输入无法解析：引号不匹配、未知命令、编码错误等
+    Invalid(String),
+    Store(TitaniumError),
+}
+
+impl From<TitaniumError> for ReplError {
+    fn from(e: TitaniumError) -> Self {
+        ReplError::Store(e)
+    }
+}
+
+/// 交互式命令行，参数按 Redis 的规则解析引号和转义
+pub struct Repl {
+    kv: KVStore,
+    mode: Mode,
+}
+
+impl Repl {
+    pub fn new(kv: KVStore) -> Self {
+        Self {
+            kv,
+            mode: Mode::Text,
+        }
+    }
+
+    pub fn run(mut self) -> Result<(), TitaniumError> {
+        println!("Welcome to Titanium KV Store!");
+        println!("{}", HELP);
+
+        let mut input = String::new();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            input.clear();
+            if io::stdin().read_line(&mut input)? == 0 {
+                break; // EOF
+            }
+            match self.execute(&input) {
+                Ok(Outcome::Print(output)) => println!("{}", output),
+                Ok(Outcome::Continue) => {}
+                Ok(Outcome::Exit) => break,
+                Err(ReplError::Usage(usage)) => println!("Usage: {}", usage),
+                Err(ReplError::Invalid(message)) => println!("{}", message),
+                Err(ReplError::Store(e)) => eprintln!("Error: {}", e),
+            }
+        }
+        self.kv.close()
+    }
+
+    fn execute(&mut self, line: &str) -> Result<Outcome, ReplError> {
+        let mut args = split_args(line)
+            .map_err(|e| ReplError::Invalid(format!("Invalid argument(s): {}", e)))?
+            .into_iter();
+        let Some(command) = args.next() else {
+            return Ok(Outcome::Continue);
+        };
+        let command = String::from_utf8_lossy(&command).to_uppercase();
+        match command.as_str() {
+            "EXIT" | "QUIT" => Ok(Outcome::Exit),
+            "HELP" => Ok(Outcome::Print(HELP.to_string())),
+            _ => self.dispatch(&command, args.collect()).map(Outcome::Print),
+        }
+    }
+
+    fn dispatch(&mut self, command: &str, args: Vec<Vec<u8>>) -> Result<String, ReplError> {
+        let mode = self.mode;
+        let kv = &mut self.kv;
+        let output = match command {
+            "SET" => {
+                let [key, value] = exact(args, "SET <key> <value>")?;
+                kv.set(key, mode.decode(value)?)?;
+                "OK".to_string()
+            }
+            "SETEX" => {
+                let [key, seconds, value] = exact(args, "SETEX <key> <seconds> <value>")?;
+                let seconds = match parse_u64(&seconds) {
+                    Some(seconds) if seconds > 0 => seconds,
+                    _ => return Err(ReplError::Usage("SETEX <key> <seconds> <value>")),
+                };
+                kv.set_with_ttl(key, mode.decode(value)?, Duration::from_secs(seconds))?;
+                "OK".to_string()
+            }
+            "GET" => {
+                let [key] = exact(args, "GET <key>")?;
+                match kv.get(key)? {
+                    Some(entry) => mode.encode(&entry.value),
+                    None => "(nil)".to_string(),
+                }
+            }
+            "RM" => {
+                let [key] = exact(args, "RM <key>")?;
+                kv.remove(key)?;
+                "OK".to_string()
+            }
+            "EXISTS" => {
+                if args.is_empty() {
+                    return Err(ReplError::Usage("EXISTS <key> [key ...]"));
+                }
+                let mut count = 0;
+                for key in args {
+                    if kv.get(key)?.is_some() {
+                        count += 1;
+                    }
+                }
+                format!("(integer) {}", count)
+            }
+            "TTL" => {
+                let [key] = exact(args, "TTL <key>")?;
+                // 与 Redis 一致：Key 不存在返回 -2，没有设置 TTL 返回 -1
+                let ttl = match kv.get(key)? {
+                    None => -2,
+                    Some(entry) => match entry.expire_at() {
+                        None => -1,
+                        Some(expire_at) => {
+                            let now = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis() as u64;
+                            expire_at.saturating_sub(now).div_ceil(1000) as i64
+                        }
+                    },
+                };
+                format!("(integer) {}", ttl)
+            }
+            "KEYS" => {
+                let [pattern] = exact(args, "KEYS <pattern>")?;
+                let mut keys = Vec::new();
+                for key in kv.keys() {
+                    let key = key?;
+                    if glob_match(&pattern, key) {
+                        keys.push(key.to_vec());
+                    }
+                }
+                keys.sort();
+                if keys.is_empty() {
+                    "(empty list)".to_string()
+                } else {
+                    keys.iter()
+                        .enumerate()
+                        .map(|(i, key)| format!("{}) {}", i + 1, quote(key)))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            "SETNX" | "SETXX" => {
+                let usage = if command == "SETNX" {
+                    "SETNX <key> <value>"
+                } else {
+                    "SETXX <key> <value>"
+                };
+                let [key, value] = exact(args, usage)?;
+                let value = mode.decode(value)?;
+                conditional(if command == "SETNX" {
+                    kv.set_if_absent(key, value)?
+                } else {
+                    kv.set_if_present(key, value)?
+                })
+            }
+            "CAS" => {
+                // CAS <key> <expected> <new>，expected 为 (nil) 表示要求 Key 不存在
+                let [key, expected, new] = exact(args, "CAS <key> <expected|(nil)> <new>")?;
+                let expected = match expected.as_slice() {
+                    b"(nil)" => None,
+                    _ => Some(mode.decode(expected)?),
+                };
+                conditional(kv.compare_and_swap(key, expected.as_deref(), mode.decode(new)?)?)
+            }
+            "VERSION" => {
+                let [key] = exact(args, "VERSION <key>")?;
+                match kv.get(key)? {
+                    Some(entry) => entry.sequence_number.to_string(),
+                    None => "(nil)".to_string(),
+                }
+            }
+            "SETVER" => {
+                let [key, version, value] = exact(args, "SETVER <key> <version> <value>")?;
+                let Some(version) = parse_u64(&version) else {
+                    return Err(ReplError::Usage("SETVER <key> <version> <value>"));
+                };
+                conditional(kv.set_if_version(key, version, mode.decode(value)?)?)
+            }
+            "MODE" => match args.as_slice() {
+                [] => self.mode.name().to_string(),
+                [name] => {
+                    self.mode = match name.to_ascii_lowercase().as_slice() {
+                        b"text" => Mode::Text,
+                        b"hex" => Mode::Hex,
+                        b"base64" => Mode::Base64,
+                        _ => return Err(ReplError::Usage("MODE [text|hex|base64]")),
+                    };
+                    "OK".to_string()
+                }
+                _ => return Err(ReplError::Usage("MODE [text|hex|base64]")),
+            },
+            _ => return Err(ReplError::Invalid(format!("Unknown command: {}", command))),
+        };
+        Ok(output)
+    }
+}
+
+/// 要求恰好 N 个参数
+fn exact<const N: usize>(
+    args: Vec<Vec<u8>>,
+    usage: &'static str,
+) -> Result<[Vec<u8>; N], ReplError> {
+    args.try_into().map_err(|_| ReplError::Usage(usage))
+}
+
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+/// 条件写入的结果：写入成功输出 OK，条件不满足输出 (nil)
+fn conditional(written: bool) -> String {
+    if written { "OK" } else { "(nil)" }.to_string()
+}
+
+/// 按 redis-cli 的规则拆分参数
+///
+/// - 空白分隔参数；
+/// - `"..."` 内支持 `\n` `\r` `\t` `\b` `\a` `\\` `\"` 和 `\xNN`，其他 `\c` 保留为 `c`；
+/// - `'...'` 内只有 `\'` 是转义；
+/// - 引号只能包住整个参数：开引号必须在参数开头 (`a"b c"` 是错误)，闭引号之后必须是空白或行尾。
+fn split_args(line: &str) -> Result<Vec<Vec<u8>>, &'static str> {
+    let bytes = line.as_bytes();
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if i == bytes.len() {
+            return Ok(args);
+        }
+
+        let start = i;
+        let mut arg = Vec::new();
+        let mut quote = None;
+        loop {
+            let Some(&c) = bytes.get(i) else {
+                if quote.is_some() {
+                    return Err("unbalanced quotes");
+                }
+                break;
+            };
+            match quote {
+                None => match c {
+                    c if c.is_ascii_whitespace() => break,
+                    b'"' | b'\'' if i > start => return Err("quotes must start an argument"),
+                    b'"' | b'\'' => quote = Some(c),
+                    c => arg.push(c),
+                },
+                Some(q) if c == q => {
+                    if bytes.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return Err("closing quote must be followed by a space");
+                    }
+                    quote = None;
+                }
+                Some(b'"') if c == b'\\' && i + 1 < bytes.len() => {
+                    let hex = bytes
+                        .get(i + 2..i + 4)
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+                    match (bytes[i + 1], hex) {
+                        (b'x', Some(b)) => {
+                            arg.push(b);
+                            i += 2;
+                        }
+                        (b'n', _) => arg.push(b'\n'),
+                        (b'r', _) => arg.push(b'\r'),
+                        (b't', _) => arg.push(b'\t'),
+                        (b'b', _) => arg.push(0x08),
+                        (b'a', _) => arg.push(0x07),
+                        (c, _) => arg.push(c),
+                    }
+                    i += 1;
+                }
+                Some(_) if c == b'\\' && bytes.get(i + 1) == Some(&b'\'') => {
+                    arg.push(b'\'');
+                    i += 1;
+                }
+                Some(_) => arg.push(c),
+            }
+            i += 1;
+        }
+        args.push(arg);
+    }
+}
+
+/// 输出为带引号的字符串：可打印字符原样输出 (包括非 ASCII 的 UTF-8 字符)，
+/// 控制字符和非法 UTF-8 字节转义，结果可以原样粘贴回命令行
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c.is_control() => {
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        out.push_str(&format!("\\x{:02x}", b));
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use titanium_engine::config::ConfigWatcher;
+    use titanium_engine::storage::MemFileSystem;
+
+    fn create_repl() -> Repl {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "/repl".to_string();
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap();
+        kv.restore().unwrap();
+        Repl::new(kv)
+    }
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        match repl.execute(line) {
+            Ok(Outcome::Print(output)) => output,
+            Ok(Outcome::Continue) => String::new(),
+            Ok(Outcome::Exit) => "<exit>".to_string(),
+            Err(ReplError::Usage(usage)) => format!("Usage: {}", usage),
+            Err(ReplError::Invalid(message)) => message,
+            Err(ReplError::Store(e)) => panic!("store error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_split_args() {
+        let split = |line| split_args(line).unwrap();
+        assert_eq!(
+            split("  SET  k   v \n"),
+            vec![b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]
+        );
+        assert_eq!(
+            split(r#"SET "my key" " lead\n\x00\xff\"""#),
+            vec![
+                b"SET".to_vec(),
+                b"my key".to_vec(),
+                b" lead\n\x00\xff\"".to_vec()
+            ]
+        );
+        assert_eq!(
+            split(r#"'it\'s' 'a\nb'"#),
+            vec![b"it's".to_vec(), b"a\\nb".to_vec()]
+        );
+        assert_eq!(split(r#""""#), vec![Vec::<u8>::new()]);
+        assert!(split("").is_empty());
+
+        assert_eq!(split_args(r#"GET "k"#), Err("unbalanced quotes"));
+        assert_eq!(split_args(r#"GET 'k"#), Err("unbalanced quotes"));
+        assert_eq!(
+            split_args(r#"SET a"b c""#),
+            Err("quotes must start an argument")
+        );
+        assert_eq!(
+            split_args(r#"GET k'v'"#),
+            Err("quotes must start an argument")
+        );
+        assert_eq!(
+            split_args(r#"GET "k"x"#),
+            Err("closing quote must be followed by a space")
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(b"plain"), "\"plain\"");
+        assert_eq!(quote("中文".as_bytes()), "\"中文\"");
+        assert_eq!(quote(b"a\"b\\c\nd\x00\xff"), r#""a\"b\\c\nd\x00\xff""#);
+        // 输出可以被 split_args 还原
+        let raw = b"\x01 \"x\"\r\n\xfe";
+        assert_eq!(split_args(&quote(raw)).unwrap(), vec![raw.to_vec()]);
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut repl = create_repl();
+        assert_eq!(run(&mut repl, r#"SET "my key" "  hello\nworld""#), "OK");
+        assert_eq!(run(&mut repl, r#"get "my key""#), r#""  hello\nworld""#);
+        assert_eq!(run(&mut repl, "SET k a b"), "Usage: SET <key> <value>");
+        assert_eq!(run(&mut repl, "GET missing"), "(nil)");
+        assert_eq!(
+            run(&mut repl, r#"GET "x"#),
+            "Invalid argument(s): unbalanced quotes"
+        );
+        assert_eq!(run(&mut repl, "NOPE"), "Unknown command: NOPE");
+        assert_eq!(run(&mut repl, "   "), "");
+
+        assert_eq!(run(&mut repl, "SETEX temp 100 v"), "OK");
+        let ttl = run(&mut repl, "TTL temp");
+        let ttl: i64 = ttl.strip_prefix("(integer) ").unwrap().parse().unwrap();
+        // 剩余时间取决于执行速度，只检查范围
+        assert!((90..=100).contains(&ttl), "ttl = {}", ttl);
+        assert_eq!(run(&mut repl, r#"TTL "my key""#), "(integer) -1");
+        assert_eq!(run(&mut repl, "TTL missing"), "(integer) -2");
+        assert_eq!(
+            run(&mut repl, "SETEX temp 0 v"),
+            "Usage: SETEX <key> <seconds> <value>"
+        );
+
+        assert_eq!(
+            run(&mut repl, r#"EXISTS temp "my key" missing"#),
+            "(integer) 2"
+        );
+        assert_eq!(run(&mut repl, "KEYS *"), "1) \"my key\"\n2) \"temp\"");
+        assert_eq!(run(&mut repl, "KEYS t*"), "1) \"temp\"");
+        assert_eq!(run(&mut repl, "KEYS nothing*"), "(empty list)");
+
+        assert_eq!(run(&mut repl, "SETNX temp v"), "(nil)");
+        assert_eq!(run(&mut repl, "CAS temp v w"), "OK");
+        assert_eq!(run(&mut repl, "CAS new (nil) x"), "OK");
+        assert_eq!(run(&mut repl, "RM temp"), "OK");
+        assert_eq!(run(&mut repl, "EXISTS temp"), "(integer) 0");
+        assert_eq!(run(&mut repl, "exit"), "<exit>");
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut repl = create_repl();
+        assert_eq!(run(&mut repl, "MODE"), "text");
+        assert_eq!(run(&mut repl, r#"SET bin "\x00\xff""#), "OK");
+        assert_eq!(run(&mut repl, "GET bin"), r#""\x00\xff""#);
+
+        assert_eq!(run(&mut repl, "MODE hex"), "OK");
+        assert_eq!(run(&mut repl, "GET bin"), "00ff");
+        assert_eq!(run(&mut repl, "SET h 68690a"), "OK");
+        assert_eq!(run(&mut repl, "SET h xyz"), "Invalid hex value");
+
+        assert_eq!(run(&mut repl, "MODE BASE64"), "OK");
+        assert_eq!(run(&mut repl, "GET h"), "aGkK");
+        assert_eq!(run(&mut repl, "SET b /wA="), "OK");
+        assert_eq!(run(&mut repl, "MODE text"), "OK");
+        assert_eq!(run(&mut repl, "GET h"), r#""hi\n""#);
+        assert_eq!(run(&mut repl, "GET b"), r#""\xff\x00""#);
+        assert_eq!(
+            run(&mut repl, "MODE utf16"),
+            "Usage: MODE [text|hex|base64]"
+        );
+    }
+}
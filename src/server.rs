@@ -0,0 +1,218 @@
+mod commands;
+mod http;
+mod resp;
+#[cfg(unix)]
+mod unix;
+
+pub use commands::execute;
+pub use http::HttpServer;
+pub use resp::{RespValue, read_command};
+#[cfg(unix)]
+pub use unix::bind_unix;
+
+use crate::error::TitaniumError;
+use crate::shared::SharedKVStore;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::thread;
+
+/// 兼容 Redis RESP2 协议的服务器，每个连接一个线程，共享同一个 `SharedKVStore`
+///
+/// 支持 GET、SET (EX/PX/NX/XX)、DEL、EXISTS、TTL/PTTL、PING、SCAN、INFO 和 QUIT，
+/// 可以直接使用 redis-cli 或 Redis 客户端库访问。
+///
+/// 读命令并发执行，写命令经由写锁串行化，`WriteMod::Sync` 下并发写入通过组提交合并 fsync。
+/// TCP 和 Unix domain socket (`serve_unix`) 上的连接使用同一套命令处理逻辑。
+#[derive(Clone)]
+pub struct Server {
+    db: SharedKVStore,
+}
+
+impl Server {
+    pub fn new(db: SharedKVStore) -> Self {
+        Self { db }
+    }
+
+    /// 绑定地址并开始服务，正常情况下不会返回
+    pub fn serve_tcp(&self, addr: impl ToSocketAddrs) -> Result<(), TitaniumError> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Listening on {}", listener.local_addr()?);
+        self.serve(listener)
+    }
+
+    /// 在已绑定的监听器上接受连接，为每个连接启动一个线程
+    pub fn serve(&self, listener: TcpListener) -> Result<(), TitaniumError> {
+        for stream in listener.incoming() {
+            let stream = stream.and_then(|stream| {
+                stream.set_nodelay(true)?;
+                Ok((stream.try_clone()?, stream))
+            });
+            match stream {
+                Ok((reader, writer)) => self.spawn_connection(reader, writer),
+                Err(e) => eprintln!("Accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// 在独立线程中处理一个连接
+    fn spawn_connection(
+        &self,
+        reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) {
+        let server = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = server.handle_connection(reader, writer) {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+    }
+
+    /// 处理一个连接直到客户端断开或发送 QUIT
+    ///
+    /// 支持流水线：客户端可以连续发送多个命令而不等待回复。回复先写入缓冲区，
+    /// 只有读缓冲区中没有剩余命令时才刷新，一批流水线命令的回复合并为一次写出。
+    /// 协议错误时回复 `-ERR Protocol error` 并关闭连接。
+    pub fn handle_connection(&self, reader: impl Read, writer: impl Write) -> io::Result<()> {
+        let (max_key, max_val) = self.db.read(|kv| kv.config.max_sizes());
+        let max_bulk_len = max_key.max(max_val);
+        let mut reader = BufReader::new(reader);
+        let mut writer = BufWriter::new(writer);
+        loop {
+            let args = match read_command(&mut reader, max_bulk_len) {
+                Ok(Some(args)) => args,
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    RespValue::error(format!("ERR Protocol error: {}", e))
+                        .encode_to(&mut writer)?;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            if args.is_empty() {
+                continue;
+            }
+
+            if args[0].eq_ignore_ascii_case(b"QUIT") {
+                RespValue::ok().encode_to(&mut writer)?;
+                break;
+            }
+            execute(&self.db, &args).encode_to(&mut writer)?;
+            if reader.buffer().is_empty() {
+                writer.flush()?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigWatcher;
+    use crate::kv::KVStore;
+    use crate::storage::MemFileSystem;
+    use std::io::BufRead;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn start_server() -> (SharedKVStore, std::net::SocketAddr) {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "/server".to_string();
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap();
+        kv.restore().unwrap();
+        let db = kv.into_shared().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(db.clone());
+        thread::spawn(move || server.serve(listener));
+        (db, addr)
+    }
+
+    fn encode_command(args: &[&str]) -> Vec<u8> {
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            buf.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+        }
+        buf
+    }
+
+    fn read_lines(reader: &mut impl BufRead, n: usize) -> Vec<String> {
+        (0..n)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tcp_pipelining() {
+        let (db, addr) = start_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        // 一次写出多个命令，不等待回复
+        let mut request = Vec::new();
+        request.extend(encode_command(&["SET", "k", "hello"]));
+        request.extend(encode_command(&["GET", "k"]));
+        request.extend(encode_command(&["EXISTS", "k", "missing"]));
+        request.extend(encode_command(&["DEL", "k"]));
+        request.extend(encode_command(&["GET", "k"]));
+        request.extend(b"PING\r\n");
+        stream.write_all(&request).unwrap();
+
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(
+            read_lines(&mut reader, 7),
+            vec!["+OK", "$5", "hello", ":1", ":1", "$-1", "+PONG"]
+        );
+
+        stream.write_all(&encode_command(&["QUIT"])).unwrap();
+        assert_eq!(read_lines(&mut reader, 1), vec!["+OK"]);
+        // 服务器在 QUIT 之后关闭连接
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+        assert!(db.get("k").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_connections() {
+        let (db, addr) = start_server();
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                thread::spawn(move || {
+                    let mut stream = TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    for i in 0..20 {
+                        let key = format!("t{}:{}", t, i);
+                        stream
+                            .write_all(&encode_command(&["SET", &key, "v"]))
+                            .unwrap();
+                        assert_eq!(read_lines(&mut reader, 1), vec!["+OK"]);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(db.stats().unwrap().keys, 80);
+    }
+
+    #[test]
+    fn test_protocol_error_closes_connection() {
+        let (_db, addr) = start_server();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"*1\r\n+GET\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let reply = read_lines(&mut reader, 1);
+        assert!(reply[0].starts_with("-ERR Protocol error"));
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).unwrap(), 0);
+    }
+}
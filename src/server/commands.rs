@@ -0,0 +1,490 @@
+use super::resp::RespValue;
+use crate::error::TitaniumError;
+use crate::kv::MAX_TTL;
+use crate::shared::SharedKVStore;
+use crate::utils::glob_match;
+use std::collections::BinaryHeap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// SCAN 未指定 COUNT 时每页返回的 Key 数
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// 执行一个命令，`args[0]` 为命令名 (不区分大小写)
+///
+/// 存储层的错误转换为 `-ERR` 回复，不会中断连接。
+pub fn execute(db: &SharedKVStore, args: &[Vec<u8>]) -> RespValue {
+    let Some(name) = args.first() else {
+        return RespValue::error("ERR empty command");
+    };
+    let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+    let result = match name.as_str() {
+        "ping" => ping(args),
+        "get" => get(db, args),
+        "set" => set(db, args),
+        "del" => del(db, args),
+        "exists" => exists(db, args),
+        "ttl" => ttl(db, args, false),
+        "pttl" => ttl(db, args, true),
+        "scan" => scan(db, args),
+        "info" => info(db, args),
+        _ => {
+            return RespValue::error(format!(
+                "ERR unknown command '{}'",
+                String::from_utf8_lossy(&args[0])
+            ));
+        }
+    };
+    result.unwrap_or_else(|e| match e {
+        TitaniumError::ReadOnly => {
+            RespValue::error("READONLY You can't write against a read only store.")
+        }
+        e => RespValue::error(format!("ERR {}", e)),
+    })
+}
+
+fn wrong_arity(name: &str) -> RespValue {
+    RespValue::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name
+    ))
+}
+
+fn syntax_error() -> RespValue {
+    RespValue::error("ERR syntax error")
+}
+
+fn not_an_integer() -> RespValue {
+    RespValue::error("ERR value is not an integer or out of range")
+}
+
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+fn ping(args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    Ok(match args {
+        [_] => RespValue::Simple("PONG".to_string()),
+        [_, message] => RespValue::Bulk(Some(message.clone())),
+        _ => wrong_arity("ping"),
+    })
+}
+
+fn get(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    let [_, key] = args else {
+        return Ok(wrong_arity("get"));
+    };
+    Ok(RespValue::Bulk(db.get(key)?.map(|entry| entry.value)))
+}
+
+/// `SET key value [EX seconds | PX milliseconds] [NX | XX]`
+///
+/// NX/XX 的存在性判断和写入在同一次写锁内完成；条件不满足时返回 Null。
+fn set(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    let [_, key, value, options @ ..] = args else {
+        return Ok(wrong_arity("set"));
+    };
+    let mut ttl = None;
+    let (mut nx, mut xx) = (false, false);
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"NX" if !xx => nx = true,
+            b"XX" if !nx => xx = true,
+            unit @ (b"EX" | b"PX") if ttl.is_none() => {
+                let Some(amount) = options.next() else {
+                    return Ok(syntax_error());
+                };
+                let Some(amount) = parse_u64(amount) else {
+                    return Ok(not_an_integer());
+                };
+                let expire = if unit == b"EX" {
+                    Duration::from_secs(amount)
+                } else {
+                    Duration::from_millis(amount)
+                };
+                // 与 Redis 一致：0 或者加上当前时间后溢出的过期时间都视为无效
+                if amount == 0 || expire > MAX_TTL {
+                    return Ok(RespValue::error("ERR invalid expire time in 'set' command"));
+                }
+                ttl = Some(expire);
+            }
+            _ => return Ok(syntax_error()),
+        }
+    }
+
+    let written = db.write(|kv| {
+        if nx || xx {
+            let exists = kv.current_version(key)?.is_some();
+            if (nx && exists) || (xx && !exists) {
+                return Ok(false);
+            }
+        }
+        match ttl {
+            Some(ttl) => kv.set_with_ttl(key.clone(), value.clone(), ttl)?,
+            None => kv.set(key.clone(), value.clone())?,
+        }
+        Ok(true)
+    })?;
+    Ok(if written {
+        RespValue::ok()
+    } else {
+        RespValue::null()
+    })
+}
+
+/// 返回实际删除的 (未过期的) Key 数
+fn del(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    if args.len() < 2 {
+        return Ok(wrong_arity("del"));
+    }
+    let removed = db.write(|kv| {
+        let mut removed = 0;
+        for key in &args[1..] {
+            if kv.current_version(key)?.is_some() {
+                kv.remove(key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    })?;
+    Ok(RespValue::Integer(removed))
+}
+
+/// 与 Redis 一致，重复出现的 Key 会被重复计数
+fn exists(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    if args.len() < 2 {
+        return Ok(wrong_arity("exists"));
+    }
+    let count = db.read(|kv| {
+        let mut count = 0;
+        for key in &args[1..] {
+            if kv.current_version(key)?.is_some() {
+                count += 1;
+            }
+        }
+        Ok::<_, TitaniumError>(count)
+    })?;
+    Ok(RespValue::Integer(count))
+}
+
+/// 剩余存活时间：Key 不存在返回 -2，没有设置 TTL 返回 -1
+fn ttl(db: &SharedKVStore, args: &[Vec<u8>], millis: bool) -> Result<RespValue, TitaniumError> {
+    let [_, key] = args else {
+        return Ok(wrong_arity(if millis { "pttl" } else { "ttl" }));
+    };
+    let Some(entry) = db.get(key)? else {
+        return Ok(RespValue::Integer(-2));
+    };
+    let Some(expire_at) = entry.expire_at() else {
+        return Ok(RespValue::Integer(-1));
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let remaining = expire_at.saturating_sub(now);
+    Ok(RespValue::Integer(if millis {
+        remaining as i64
+    } else {
+        remaining.div_ceil(1000) as i64
+    }))
+}
+
+/// Key 在 SCAN 遍历顺序中的位置：crc32 + 1，使游标 0 只表示开始和结束
+fn scan_slot(key: &[u8]) -> u64 {
+    crc32fast::hash(key) as u64 + 1
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count]`
+///
+/// 游标是 Key 的哈希槽位 (`scan_slot`)，每页返回槽位不小于游标的前 COUNT 个 Key，
+/// 下一个游标为下一个未返回 Key 的槽位。槽位与索引结构无关，因此整个遍历期间一直存在的 Key
+/// 至少返回一次；同一槽位的 Key 总是在同一页返回，不会因哈希冲突而遗漏。
+/// 每次调用遍历两遍索引中的 Key (不读磁盘)，只保留一页；选出一页之后再按 MATCH 过滤并跳过已过期的 Key，
+/// 只有这一页的 Key 需要读取条目 Header，因此与 Redis 一样可能返回不足 COUNT 个甚至空页。
+fn scan(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    let [_, cursor, options @ ..] = args else {
+        return Ok(wrong_arity("scan"));
+    };
+    let Some(cursor) = parse_u64(cursor) else {
+        return Ok(RespValue::error("ERR invalid cursor"));
+    };
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    for option in options.chunks(2) {
+        match (option[0].to_ascii_uppercase().as_slice(), option.get(1)) {
+            (b"MATCH", Some(p)) => pattern = Some(p.as_slice()),
+            (b"COUNT", Some(n)) => match parse_u64(n) {
+                Some(n) if n > 0 => count = n as usize,
+                Some(_) => return Ok(syntax_error()),
+                None => return Ok(not_an_integer()),
+            },
+            _ => return Ok(syntax_error()),
+        }
+    }
+
+    let (keys, next) = db.read(|kv| {
+        // 第一遍：用大小为 COUNT 的大顶堆找出本页的最后一个槽位，不收集整个 Key 空间
+        let mut slots = BinaryHeap::new();
+        for (key, _) in kv.indexer.iter() {
+            let slot = scan_slot(key);
+            if slot < cursor {
+                continue;
+            }
+            if slots.len() < count {
+                slots.push(slot);
+            } else if slots.peek().is_some_and(|&max| slot < max) {
+                slots.pop();
+                slots.push(slot);
+            }
+        }
+        let last = match slots.peek() {
+            Some(&max) if slots.len() == count => max,
+            _ => u64::MAX,
+        };
+
+        // 第二遍：取出槽位在 [cursor, last] 内的 Key (包括与最后一个槽位相同的所有 Key)，
+        // 以及之后最小的槽位作为下一个游标
+        let mut page = Vec::new();
+        let mut next = None;
+        for (key, _) in kv.indexer.iter() {
+            let slot = scan_slot(key);
+            if slot < cursor {
+                continue;
+            }
+            if slot <= last {
+                page.push((slot, key));
+            } else if next.is_none_or(|n| slot < n) {
+                next = Some(slot);
+            }
+        }
+        page.sort_unstable();
+        let mut keys = Vec::new();
+        for (_, key) in page {
+            if pattern.is_none_or(|p| glob_match(p, key)) && kv.current_version(key)?.is_some() {
+                keys.push(key.to_vec());
+            }
+        }
+        Ok::<_, TitaniumError>((keys, next.unwrap_or(0)))
+    })?;
+    Ok(RespValue::Array(vec![
+        RespValue::Bulk(Some(next.to_string().into_bytes())),
+        RespValue::Array(
+            keys.into_iter()
+                .map(|key| RespValue::Bulk(Some(key)))
+                .collect(),
+        ),
+    ]))
+}
+
+/// 以 Redis INFO 的 `field:value` 格式返回运行状态
+fn info(db: &SharedKVStore, args: &[Vec<u8>]) -> Result<RespValue, TitaniumError> {
+    if args.len() > 2 {
+        return Ok(wrong_arity("info"));
+    }
+    let stats = db.stats()?;
+    let text = format!(
+        "# Server\r\n\
+         titanium_version:{}\r\n\
+         \r\n\
+         # Persistence\r\n\
+         read_only:{}\r\n\
+         data_files:{}\r\n\
+         disk_usage:{}\r\n\
+         pending_compaction_files:{}\r\n\
+         sequence_number:{}\r\n\
+         \r\n\
+         # Keyspace\r\n\
+         keys:{}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        u8::from(stats.read_only),
+        stats.data_files,
+        stats.disk_usage,
+        stats.pending_compaction_files,
+        stats.sequence_number,
+        stats.keys,
+    );
+    Ok(RespValue::Bulk(Some(text.into_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigWatcher;
+    use crate::kv::KVStore;
+    use crate::storage::MemFileSystem;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn create_db() -> SharedKVStore {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "/db".to_string();
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap();
+        kv.restore().unwrap();
+        kv.into_shared().unwrap()
+    }
+
+    fn run(db: &SharedKVStore, command: &str) -> RespValue {
+        let args: Vec<Vec<u8>> = command
+            .split_whitespace()
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect();
+        execute(db, &args)
+    }
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(Some(s.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_basic_commands() {
+        let db = create_db();
+        assert_eq!(run(&db, "PING"), RespValue::Simple("PONG".to_string()));
+        assert_eq!(run(&db, "ping hi"), bulk("hi"));
+        assert_eq!(run(&db, "GET k"), RespValue::null());
+        assert_eq!(run(&db, "SET k v"), RespValue::ok());
+        assert_eq!(run(&db, "get k"), bulk("v"));
+        assert_eq!(run(&db, "EXISTS k k missing"), RespValue::Integer(2));
+        assert_eq!(run(&db, "DEL k missing"), RespValue::Integer(1));
+        assert_eq!(run(&db, "DEL k"), RespValue::Integer(0));
+        assert_eq!(run(&db, "GET k"), RespValue::null());
+
+        assert!(matches!(run(&db, "GET"), RespValue::Error(e) if e.contains("wrong number")));
+        assert!(matches!(run(&db, "FLUSHALL"), RespValue::Error(e) if e.contains("unknown")));
+    }
+
+    #[test]
+    fn test_set_options() {
+        let db = create_db();
+        assert_eq!(run(&db, "SET k v1 XX"), RespValue::null());
+        assert_eq!(run(&db, "SET k v1 NX"), RespValue::ok());
+        assert_eq!(run(&db, "SET k v2 NX"), RespValue::null());
+        assert_eq!(run(&db, "SET k v2 xx"), RespValue::ok());
+        assert_eq!(run(&db, "GET k"), bulk("v2"));
+
+        assert_eq!(run(&db, "TTL k"), RespValue::Integer(-1));
+        assert_eq!(run(&db, "TTL missing"), RespValue::Integer(-2));
+        assert_eq!(run(&db, "SET k v3 EX 100"), RespValue::ok());
+        assert!(matches!(run(&db, "TTL k"), RespValue::Integer(n) if (90..=100).contains(&n)));
+        assert_eq!(run(&db, "SET short v PX 20"), RespValue::ok());
+        assert!(matches!(run(&db, "PTTL short"), RespValue::Integer(n) if (1..=20).contains(&n)));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(run(&db, "GET short"), RespValue::null());
+        assert_eq!(run(&db, "TTL short"), RespValue::Integer(-2));
+        // 过期的 Key 视为不存在
+        assert_eq!(run(&db, "SET short v NX"), RespValue::ok());
+
+        assert_eq!(run(&db, "SET k v NX XX"), syntax_error());
+        assert_eq!(run(&db, "SET k v EX 1 PX 1"), syntax_error());
+        assert_eq!(run(&db, "SET k v EX"), syntax_error());
+        assert_eq!(run(&db, "SET k v EX abc"), not_an_integer());
+        assert!(matches!(run(&db, "SET k v EX 0"), RespValue::Error(e) if e.contains("expire")));
+        for huge in [
+            "EX 18446744073709551615",
+            "PX 18446744073709551615",
+            "EX 9223372036854776",
+        ] {
+            let reply = run(&db, &format!("SET k v {}", huge));
+            assert_eq!(
+                reply,
+                RespValue::error("ERR invalid expire time in 'set' command")
+            );
+        }
+        // 被拒绝的 SET 不会修改原有的值
+        assert_eq!(run(&db, "GET k"), bulk("v3"));
+    }
+
+    fn scan_page(db: &SharedKVStore, command: &str) -> (String, Vec<Vec<u8>>) {
+        let RespValue::Array(reply) = run(db, command) else {
+            panic!("SCAN should return an array");
+        };
+        let [RespValue::Bulk(Some(cursor)), RespValue::Array(keys)] = reply.as_slice() else {
+            panic!("unexpected SCAN reply: {:?}", reply);
+        };
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                RespValue::Bulk(Some(key)) => key.clone(),
+                other => panic!("unexpected key: {:?}", other),
+            })
+            .collect();
+        (String::from_utf8(cursor.clone()).unwrap(), keys)
+    }
+
+    #[test]
+    fn test_scan() {
+        let db = create_db();
+        for i in 0..25 {
+            db.set(format!("user:{}", i), b"v".to_vec()).unwrap();
+        }
+        for i in 0..5 {
+            db.set(format!("order:{}", i), b"v".to_vec()).unwrap();
+        }
+
+        // 按页遍历完整个 Key 空间，每个 Key 恰好返回一次
+        let mut seen = HashSet::new();
+        let mut cursor = "0".to_string();
+        let mut pages = 0;
+        loop {
+            let (next, keys) = scan_page(&db, &format!("SCAN {} COUNT 7", cursor));
+            for key in keys {
+                assert!(seen.insert(key));
+            }
+            pages += 1;
+            cursor = next;
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 30);
+        assert!(pages >= 5);
+
+        // COUNT 大于 Key 总数时一页返回全部，不按 COUNT 预分配
+        let (next, keys) = scan_page(&db, "SCAN 0 COUNT 1000000000000");
+        assert_eq!(next, "0");
+        assert_eq!(keys.len(), 30);
+
+        let mut matched = HashSet::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let (next, keys) = scan_page(&db, &format!("SCAN {} MATCH order:* COUNT 4", cursor));
+            matched.extend(keys);
+            cursor = next;
+            if cursor == "0" {
+                break;
+            }
+        }
+        let expected: HashSet<Vec<u8>> = (0..5)
+            .map(|i| format!("order:{}", i).into_bytes())
+            .collect();
+        assert_eq!(matched, expected);
+
+        // 已过期的 Key 不会返回
+        db.set_with_ttl("user:0", b"v".to_vec(), Duration::from_millis(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        let (_, keys) = scan_page(&db, "SCAN 0 COUNT 100");
+        assert_eq!(keys.len(), 29);
+        assert!(!keys.contains(&b"user:0".to_vec()));
+
+        assert_eq!(run(&db, "SCAN 0 COUNT 0"), syntax_error());
+        assert_eq!(run(&db, "SCAN 0 MATCH"), syntax_error());
+        assert!(matches!(run(&db, "SCAN x"), RespValue::Error(_)));
+    }
+
+    #[test]
+    fn test_info() {
+        let db = create_db();
+        run(&db, "SET a 1");
+        run(&db, "SET b 2");
+        let RespValue::Bulk(Some(text)) = run(&db, "INFO") else {
+            panic!("INFO should return a bulk string");
+        };
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("keys:2\r\n"));
+        assert!(text.contains("read_only:0\r\n"));
+        assert!(text.contains("sequence_number:2\r\n"));
+    }
+}
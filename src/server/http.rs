@@ -0,0 +1,610 @@
+use crate::error::TitaniumError;
+use crate::kv::MAX_TTL;
+use crate::shared::SharedKVStore;
+use crate::utils::json_string;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 请求行和每个 Header 行的最大长度
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// 单个请求最多包含的 Header 数
+const MAX_HEADERS: usize = 100;
+/// 通过 Header 指定 TTL (秒)
+const TTL_HEADER: &str = "x-ttl";
+
+/// HTTP/JSON 接口，每个连接一个线程，共享同一个 `SharedKVStore`
+///
+/// - `GET /kv/{key}`：返回原始字节，带 TTL 的 Key 在 `X-TTL` 中返回剩余秒数
+/// - `PUT /kv/{key}`：请求体为原始字节，TTL (秒) 通过 `?ttl=` 或 `X-TTL` 指定
+/// - `DELETE /kv/{key}`：Key 不存在时返回 404
+/// - `GET /stats`、`GET /health`：JSON
+///
+/// Key 需要按 URL 编码 (`%XX`)，因此可以是任意字节。只支持带 Content-Length 的请求体，
+/// 连接默认保持 (HTTP/1.1 keep-alive)。
+#[derive(Clone)]
+pub struct HttpServer {
+    db: SharedKVStore,
+}
+
+/// 已解析的请求
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+impl Request {
+    /// Header 名不区分大小写
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<Vec<u8>> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, v)| percent_decode(v))
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn json(status: u16, body: String) -> Self {
+        Self::new(status)
+            .header("Content-Type", "application/json")
+            .body(body.into_bytes())
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\":{}}}", json_string(message)))
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    fn write_to(&self, writer: &mut impl Write, keep_alive: bool) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason(self.status)
+        )?;
+        for (name, value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        write!(writer, "Content-Length: {}\r\n", self.body.len())?;
+        if !keep_alive {
+            writer.write_all(b"Connection: close\r\n")?;
+        }
+        writer.write_all(b"\r\n")?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+/// 读取请求失败的原因：连接错误直接关闭，协议错误先回复再关闭
+enum ReadError {
+    Io(io::Error),
+    Reject(Response),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl HttpServer {
+    pub fn new(db: SharedKVStore) -> Self {
+        Self { db }
+    }
+
+    /// 绑定地址并开始服务，正常情况下不会返回
+    pub fn serve_tcp(&self, addr: impl ToSocketAddrs) -> Result<(), TitaniumError> {
+        let listener = TcpListener::bind(addr)?;
+        println!("HTTP listening on {}", listener.local_addr()?);
+        self.serve(listener)
+    }
+
+    /// 在已绑定的监听器上接受连接，为每个连接启动一个线程
+    pub fn serve(&self, listener: TcpListener) -> Result<(), TitaniumError> {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = self.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = server.handle_tcp(stream) {
+                            eprintln!("HTTP connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_tcp(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        self.handle_connection(stream.try_clone()?, stream)
+    }
+
+    /// 处理一个连接上的请求，直到客户端断开或要求关闭连接
+    pub fn handle_connection(&self, reader: impl Read, writer: impl Write) -> io::Result<()> {
+        let mut reader = BufReader::new(reader);
+        let mut writer = BufWriter::new(writer);
+        loop {
+            let (max_key, max_val) = self.db.read(|kv| kv.config.max_sizes());
+            let request = match read_request(&mut reader, &mut writer, max_val) {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(()),
+                Err(ReadError::Reject(response)) => return response.write_to(&mut writer, false),
+                Err(ReadError::Io(e)) => return Err(e),
+            };
+            let response = self.route(&request, max_key);
+            response.write_to(&mut writer, request.keep_alive)?;
+            if !request.keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    fn route(&self, request: &Request, max_key: usize) -> Response {
+        let method = request.method.as_str();
+        let result = match request.path.as_str() {
+            "/health" => match method {
+                "GET" => Ok(Response::json(200, "{\"status\":\"ok\"}".to_string())),
+                _ => Ok(method_not_allowed("GET")),
+            },
+            "/stats" => match method {
+                "GET" => self
+                    .db
+                    .stats()
+                    .map(|stats| Response::json(200, stats.to_json())),
+                _ => Ok(method_not_allowed("GET")),
+            },
+            path => match path.strip_prefix("/kv/").map(percent_decode) {
+                Some(Some(key)) if !key.is_empty() && key.len() <= max_key => match method {
+                    "GET" => self.get(&key),
+                    "PUT" => self.put(request, key),
+                    "DELETE" => self.delete(&key),
+                    _ => Ok(method_not_allowed("GET, PUT, DELETE")),
+                },
+                Some(Some(key)) if !key.is_empty() => Ok(Response::error(400, "key too large")),
+                Some(_) => Ok(Response::error(400, "invalid key")),
+                None => Ok(Response::error(404, "not found")),
+            },
+        };
+        result.unwrap_or_else(error_response)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Response, TitaniumError> {
+        let Some(entry) = self.db.get(key)? else {
+            return Ok(Response::error(404, "key not found"));
+        };
+        let mut response = Response::new(200).header("Content-Type", "application/octet-stream");
+        if let Some(expire_at) = entry.expire_at() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            let remaining = expire_at.saturating_sub(now).div_ceil(1000);
+            response = response.header("X-TTL", remaining.to_string());
+        }
+        Ok(response.body(entry.value))
+    }
+
+    fn put(&self, request: &Request, key: Vec<u8>) -> Result<Response, TitaniumError> {
+        let ttl = match request.query_param("ttl") {
+            Some(ttl) => String::from_utf8(ttl).ok(),
+            None => request.header(TTL_HEADER).map(str::to_string),
+        };
+        let ttl = match ttl.map(|ttl| ttl.trim().parse::<u64>()) {
+            None => None,
+            Some(Ok(secs)) if secs > 0 => Some(Duration::from_secs(secs)),
+            Some(_) => return Ok(Response::error(400, "ttl must be a positive integer")),
+        };
+        if ttl.is_some_and(|ttl| ttl > MAX_TTL) {
+            return Ok(Response::error(400, "ttl is too large"));
+        }
+        let value = request.body.clone();
+        match ttl {
+            Some(ttl) => self.db.set_with_ttl(key, value, ttl)?,
+            None => self.db.set(key, value)?,
+        }
+        Ok(Response::new(204))
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<Response, TitaniumError> {
+        let removed = self.db.write(|kv| {
+            if kv.current_version(key)?.is_none() {
+                return Ok(false);
+            }
+            kv.remove(key)?;
+            Ok(true)
+        })?;
+        Ok(if removed {
+            Response::new(204)
+        } else {
+            Response::error(404, "key not found")
+        })
+    }
+}
+
+/// 读取一个请求；在请求边界上遇到 EOF 时返回 None
+///
+/// 客户端发送 `Expect: 100-continue` 时，先回复 `100 Continue` 再读取请求体。
+fn read_request(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    max_body: usize,
+) -> Result<Option<Request>, ReadError> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ReadError::Reject(Response::error(
+            400,
+            "malformed request line",
+        )));
+    };
+    let http_10 = match version {
+        "HTTP/1.1" => false,
+        "HTTP/1.0" => true,
+        _ => {
+            return Err(ReadError::Reject(Response::error(
+                505,
+                "unsupported HTTP version",
+            )));
+        }
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(ReadError::Reject(Response::error(431, "too many headers")));
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(ReadError::Reject(Response::error(400, "malformed header")));
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path,
+        query,
+        headers,
+        body: Vec::new(),
+        keep_alive: !http_10,
+    };
+    if let Some(connection) = request.header("connection") {
+        if connection.eq_ignore_ascii_case("close") {
+            request.keep_alive = false;
+        } else if connection.eq_ignore_ascii_case("keep-alive") {
+            request.keep_alive = true;
+        }
+    }
+    if request.header("transfer-encoding").is_some() {
+        return Err(ReadError::Reject(Response::error(
+            411,
+            "chunked bodies are not supported, send Content-Length",
+        )));
+    }
+    let length = match request.header("content-length").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(length)) if length <= max_body => length,
+        Some(Ok(_)) => return Err(ReadError::Reject(Response::error(413, "value too large"))),
+        Some(Err(_)) => {
+            return Err(ReadError::Reject(Response::error(
+                400,
+                "invalid Content-Length",
+            )));
+        }
+    };
+    if length > 0 {
+        if request
+            .header("expect")
+            .is_some_and(|e| e.eq_ignore_ascii_case("100-continue"))
+        {
+            writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            writer.flush()?;
+        }
+        request.body = vec![0u8; length];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(Some(request))
+}
+
+/// 读取一行 (去掉 `\r\n`)；在行首遇到 EOF 时返回 None
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, ReadError> {
+    let mut line = Vec::new();
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        if line.len() > MAX_LINE_LEN {
+            return Err(ReadError::Reject(Response::error(431, "line too long")));
+        }
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ReadError::Reject(Response::error(400, "request is not valid UTF-8")))
+}
+
+/// 解码 URL 中的 `%XX`，编码不完整时返回 None；查询参数中的 `+` 不视为空格
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+fn method_not_allowed(allow: &'static str) -> Response {
+    Response::error(405, "method not allowed").header("Allow", allow)
+}
+
+fn error_response(e: TitaniumError) -> Response {
+    let status = match e {
+        TitaniumError::ReadOnly => 403,
+        TitaniumError::SystemOverload => 503,
+        TitaniumError::DiskFull { .. } => 507,
+        _ => 500,
+    };
+    Response::error(status, &e.to_string())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        507 => "Insufficient Storage",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigWatcher;
+    use crate::kv::KVStore;
+    use crate::storage::MemFileSystem;
+    use std::sync::Arc;
+
+    fn create_server() -> HttpServer {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "/http".to_string();
+        cfg.max_val_size = 1024;
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap();
+        kv.restore().unwrap();
+        HttpServer::new(kv.into_shared().unwrap())
+    }
+
+    /// 在内存中跑完一个连接，返回服务器写出的全部内容
+    fn exchange(server: &HttpServer, request: &[u8]) -> String {
+        let mut output = Vec::new();
+        server.handle_connection(request, &mut output).unwrap();
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    fn status_line(response: &str) -> &str {
+        response.lines().next().unwrap()
+    }
+
+    fn body(response: &str) -> &str {
+        response.split_once("\r\n\r\n").unwrap().1
+    }
+
+    /// 响应中 X-TTL 头的值
+    fn ttl_header(response: &str) -> u64 {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix("X-TTL: "))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_kv_roundtrip() {
+        let server = create_server();
+        let put = exchange(
+            &server,
+            b"PUT /kv/user%3A1 HTTP/1.1\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+        );
+        assert_eq!(status_line(&put), "HTTP/1.1 204 No Content");
+        assert_eq!(
+            server.db.get("user:1").unwrap().unwrap().value,
+            b"hello".to_vec()
+        );
+
+        let get = exchange(&server, b"GET /kv/user:1 HTTP/1.0\r\n\r\n");
+        assert_eq!(status_line(&get), "HTTP/1.1 200 OK");
+        assert!(get.contains("Content-Type: application/octet-stream\r\n"));
+        assert!(!get.contains("X-TTL"));
+        assert_eq!(body(&get), "hello");
+
+        let delete = exchange(&server, b"DELETE /kv/user:1 HTTP/1.0\r\n\r\n");
+        assert_eq!(status_line(&delete), "HTTP/1.1 204 No Content");
+        let delete = exchange(&server, b"DELETE /kv/user:1 HTTP/1.0\r\n\r\n");
+        assert_eq!(status_line(&delete), "HTTP/1.1 404 Not Found");
+        let get = exchange(&server, b"GET /kv/user:1 HTTP/1.0\r\n\r\n");
+        assert_eq!(status_line(&get), "HTTP/1.1 404 Not Found");
+        assert_eq!(body(&get), "{\"error\":\"key not found\"}");
+    }
+
+    #[test]
+    fn test_ttl() {
+        let server = create_server();
+        exchange(
+            &server,
+            b"PUT /kv/a?ttl=100 HTTP/1.0\r\nContent-Length: 1\r\n\r\n1",
+        );
+        exchange(
+            &server,
+            b"PUT /kv/b HTTP/1.0\r\nX-TTL: 50\r\nContent-Length: 1\r\n\r\n2",
+        );
+        let get = exchange(&server, b"GET /kv/a HTTP/1.0\r\n\r\n");
+        // 剩余时间取决于执行速度，只检查范围
+        assert!((90..=100).contains(&ttl_header(&get)));
+        let get = exchange(&server, b"GET /kv/b HTTP/1.0\r\n\r\n");
+        assert!((40..=50).contains(&ttl_header(&get)));
+
+        let bad = exchange(
+            &server,
+            b"PUT /kv/c?ttl=0 HTTP/1.0\r\nContent-Length: 1\r\n\r\n3",
+        );
+        assert_eq!(status_line(&bad), "HTTP/1.1 400 Bad Request");
+        assert!(server.db.get("c").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_keep_alive_and_expect_continue() {
+        let server = create_server();
+        let output = exchange(
+            &server,
+            b"PUT /kv/k HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nv1\
+              GET /kv/k HTTP/1.1\r\n\r\n\
+              GET /health HTTP/1.1\r\n\r\n",
+        );
+        assert!(output.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\n"));
+        assert!(output.contains("\r\n\r\nv1HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("{\"status\":\"ok\"}"));
+        assert!(!output.contains("Connection: close"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let server = create_server();
+        server.db.set("a", b"1".to_vec()).unwrap();
+        let stats = exchange(&server, b"GET /stats HTTP/1.0\r\n\r\n");
+        assert_eq!(status_line(&stats), "HTTP/1.1 200 OK");
+        assert!(stats.contains("Content-Type: application/json\r\n"));
+        assert!(body(&stats).starts_with("{\"keys\":1,\"data_files\":1,"));
+        assert!(body(&stats).ends_with("\"sequence_number\":1,\"read_only\":false}"));
+    }
+
+    #[test]
+    fn test_rejected_requests() {
+        let server = create_server();
+        let cases: [(&[u8], &str); 9] = [
+            (b"POST /kv/k HTTP/1.0\r\n\r\n", "405 Method Not Allowed"),
+            (b"GET /nope HTTP/1.0\r\n\r\n", "404 Not Found"),
+            (b"GET /kv/ HTTP/1.0\r\n\r\n", "400 Bad Request"),
+            (b"GET /kv/%zz HTTP/1.0\r\n\r\n", "400 Bad Request"),
+            (b"GARBAGE\r\n\r\n", "400 Bad Request"),
+            (
+                b"PUT /kv/k?ttl=18446744073709551615 HTTP/1.0\r\n\r\n",
+                "400 Bad Request",
+            ),
+            (
+                b"PUT /kv/k HTTP/1.0\r\nX-TTL: 9223372036854776\r\n\r\n",
+                "400 Bad Request",
+            ),
+            (
+                b"PUT /kv/k HTTP/1.1\r\nContent-Length: 4096\r\n\r\n",
+                "413 Payload Too Large",
+            ),
+            (
+                b"PUT /kv/k HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                "411 Length Required",
+            ),
+        ];
+        for (request, status) in cases {
+            let response = exchange(&server, request);
+            assert_eq!(status_line(&response), format!("HTTP/1.1 {}", status));
+        }
+    }
+
+    #[test]
+    fn test_tcp() {
+        let server = create_server();
+        let db = server.db.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"PUT /kv/k HTTP/1.1\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert_eq!(db.get("k").unwrap().unwrap().value, b"abc".to_vec());
+    }
+}
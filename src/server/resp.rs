@@ -0,0 +1,210 @@
+use std::io::{self, BufRead, Read, Write};
+
+/// RESP2 协议的回复值
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    /// `+OK\r\n`
+    Simple(String),
+    /// `-ERR message\r\n`，内容需以错误类型开头 (如 `ERR`、`WRONGTYPE`)
+    Error(String),
+    /// `:42\r\n`
+    Integer(i64),
+    /// `$3\r\nfoo\r\n`，None 编码为 Null Bulk String (`$-1\r\n`)
+    Bulk(Option<Vec<u8>>),
+    /// `*2\r\n...`
+    Array(Vec<RespValue>),
+}
+
+impl RespValue {
+    pub fn ok() -> Self {
+        RespValue::Simple("OK".to_string())
+    }
+
+    pub fn null() -> Self {
+        RespValue::Bulk(None)
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        RespValue::Error(message.into())
+    }
+
+    pub fn encode_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            RespValue::Simple(s) => write!(writer, "+{}\r\n", s),
+            RespValue::Error(s) => write!(writer, "-{}\r\n", s),
+            RespValue::Integer(n) => write!(writer, ":{}\r\n", n),
+            RespValue::Bulk(None) => writer.write_all(b"$-1\r\n"),
+            RespValue::Bulk(Some(data)) => {
+                write!(writer, "${}\r\n", data.len())?;
+                writer.write_all(data)?;
+                writer.write_all(b"\r\n")
+            }
+            RespValue::Array(items) => {
+                write!(writer, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.encode_to(writer))
+            }
+        }
+    }
+}
+
+/// 单个命令最多包含的参数个数
+const MAX_ARGS: usize = 1024 * 1024;
+/// inline 命令和协议头部行的最大长度
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// 从连接中读取一个命令
+///
+/// 支持客户端库使用的 RESP 数组 (`*N\r\n$len\r\narg\r\n...`)，以及 telnet 等工具使用的
+/// inline 命令 (一行按空白分隔)。`max_bulk_len` 限制单个参数的长度，防止恶意请求耗尽内存。
+///
+/// 返回 None 表示连接在命令边界上正常关闭；协议错误返回 `InvalidData`。
+pub fn read_command(
+    reader: &mut impl BufRead,
+    max_bulk_len: usize,
+) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    if line.first() != Some(&b'*') {
+        return Ok(Some(
+            line.split(|c| c.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| arg.to_vec())
+                .collect(),
+        ));
+    }
+
+    let count = parse_len(&line[1..], MAX_ARGS)?;
+    let mut args = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        let header = read_line(reader)?.ok_or_else(unexpected_eof)?;
+        if header.first() != Some(&b'$') {
+            return Err(invalid_data(format!(
+                "expected '$', got '{}'",
+                String::from_utf8_lossy(&header[..header.len().min(1)])
+            )));
+        }
+        let len = parse_len(&header[1..], max_bulk_len)?;
+        let mut arg = vec![0u8; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(invalid_data("bulk string is not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// 读取一行并去掉行尾的 `\r\n` (或 `\n`)；在行首遇到 EOF 时返回 None
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(if line.len() > MAX_LINE_LEN {
+            invalid_data("line too long")
+        } else {
+            unexpected_eof()
+        });
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(digits: &[u8], max: usize) -> io::Result<usize> {
+    let len = std::str::from_utf8(digits)
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .ok_or_else(|| invalid_data("invalid length"))?;
+    if len > max {
+        return Err(invalid_data("length exceeds limit"));
+    }
+    Ok(len)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed mid-command",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_all(input: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let mut reader = Cursor::new(input.to_vec());
+        let mut commands = Vec::new();
+        while let Some(args) = read_command(&mut reader, 1024).unwrap() {
+            commands.push(args);
+        }
+        commands
+    }
+
+    #[test]
+    fn test_encode() {
+        let value = RespValue::Array(vec![
+            RespValue::ok(),
+            RespValue::error("ERR boom"),
+            RespValue::Integer(-2),
+            RespValue::Bulk(Some(b"a\r\nb".to_vec())),
+            RespValue::null(),
+        ]);
+        let mut buf = Vec::new();
+        value.encode_to(&mut buf).unwrap();
+        assert_eq!(
+            buf,
+            b"*5\r\n+OK\r\n-ERR boom\r\n:-2\r\n$4\r\na\r\nb\r\n$-1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_read_pipelined_commands() {
+        let commands = read_all(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$0\r\n\r\nPING  hello\r\n");
+        assert_eq!(
+            commands,
+            vec![
+                vec![b"GET".to_vec(), b"k".to_vec()],
+                vec![b"SET".to_vec(), b"k".to_vec(), b"".to_vec()],
+                vec![b"PING".to_vec(), b"hello".to_vec()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_binary_argument() {
+        let commands = read_all(b"*1\r\n$4\r\n\x00\r\n\xff\r\n");
+        assert_eq!(commands, vec![vec![b"\x00\r\n\xff".to_vec()]]);
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let err = |input: &[u8]| {
+            read_command(&mut Cursor::new(input.to_vec()), 1024)
+                .unwrap_err()
+                .kind()
+        };
+        assert_eq!(err(b"*1\r\n+GET\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(err(b"*x\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(err(b"*1\r\n$3\r\nGETX\r\n"), io::ErrorKind::InvalidData);
+        // 超过单个参数的长度限制
+        assert_eq!(err(b"*1\r\n$2048\r\n"), io::ErrorKind::InvalidData);
+        // 命令中途断开
+        assert_eq!(err(b"*2\r\n$3\r\nGET\r\n"), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,178 @@
+use super::Server;
+use crate::error::TitaniumError;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 在 `path` 上创建 Unix domain socket 监听器，socket 文件的权限为 `mode`
+///
+/// socket 出现在 `path` 上时权限已经是 `mode`，不存在权限更宽松的窗口 (见 `bind_private`)。
+/// 上次进程异常退出留下的 socket 文件会被删除后重新绑定；
+/// 如果仍有进程在该 socket 上监听则返回 `AddrInUse`，不会抢占。路径上是普通文件时同样拒绝。
+pub fn bind_unix(path: impl AsRef<Path>, mode: u32) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another server", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    bind_private(path, mode)
+}
+
+/// 在同目录下权限为 0700 的临时目录中绑定并设置权限，再 rename 到 `path`
+///
+/// 直接在 `path` 上绑定时，socket 文件在 chmod 之前带有 umask 决定的默认权限，
+/// 其他用户可能在这个窗口内连接；临时目录只有当前用户能进入，socket 出现在 `path` 时已经是 `mode`。
+fn bind_private(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = parent.join(format!(
+        ".titanium-{}-{}",
+        process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let tmp = dir.join("s");
+    let result = UnixListener::bind(&tmp).and_then(|listener| {
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(mode))?;
+        fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    fs::remove_dir(&dir)?;
+    result
+}
+
+impl Server {
+    /// 在 Unix domain socket 上接受连接，命令集和处理逻辑与 TCP 相同
+    pub fn serve_unix(&self, listener: UnixListener) -> Result<(), TitaniumError> {
+        for stream in listener.incoming() {
+            match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                Ok((reader, writer)) => self.spawn_connection(reader, writer),
+                Err(e) => eprintln!("Accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigWatcher;
+    use crate::kv::KVStore;
+    use crate::storage::MemFileSystem;
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("titanium-{}-{}.sock", name, std::process::id()))
+    }
+
+    fn create_server() -> Server {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "/unix".to_string();
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap();
+        kv.restore().unwrap();
+        Server::new(kv.into_shared().unwrap())
+    }
+
+    #[test]
+    fn test_serve_unix() {
+        let path = socket_path("serve");
+        let listener = bind_unix(&path, 0o600).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let server = create_server();
+        thread::spawn(move || server.serve_unix(listener));
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(b"SET k hello\r\nGET k\r\nPING\r\n")
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let lines: Vec<String> = (0..4)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end().to_string()
+            })
+            .collect();
+        assert_eq!(lines, vec!["+OK", "$5", "hello", "+PONG"]);
+
+        // 仍在监听的 socket 不能被再次绑定
+        let err = bind_unix(&path, 0o600).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bind_leaves_no_temp_dir() {
+        let dir = std::env::temp_dir().join(format!("titanium-bind-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server.sock");
+        let listener = bind_unix(&path, 0o600).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // 绑定用的临时目录已删除，目录中只剩 socket 文件
+        let entries: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(entries, vec![path.clone()]);
+
+        // rename 之后监听器仍然可用
+        drop(UnixStream::connect(&path).unwrap());
+        drop(listener);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket() {
+        let path = socket_path("stale");
+        drop(bind_unix(&path, 0o600).unwrap());
+        // 监听器已关闭，socket 文件仍然残留
+        assert!(path.exists());
+        let listener = bind_unix(&path, 0o660).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        drop(listener);
+        fs::remove_file(&path).unwrap();
+
+        let file = socket_path("regular");
+        fs::write(&file, b"not a socket").unwrap();
+        let err = bind_unix(&file, 0o600).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,522 @@
+use crate::batch::WriteBatch;
+use crate::error::TitaniumError;
+use crate::kv::{ActiveFile, KVStore, KeyDir, Stats, WriteHandle};
+use crate::log_entry::LogEntry;
+use crate::snapshot::Snapshot;
+use crate::storage::FileLock;
+use crate::syncer::SyncFile;
+use crate::transaction::Transaction;
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// 可在线程间共享的 KVStore 句柄
+///
+/// 通过 `KVStore::into_shared` 创建，clone 开销只是一次引用计数。
+/// 读路径和写路径分别加锁：
+/// - 索引和文件表 (`KeyDir`) 由读写锁保护。读操作 (`get` / `get_at` / `read`) 只持有读锁，
+///   条目通过 `RandomAccessFile::read_at` 读取，不依赖文件游标，因此多个读者可以并发执行；
+/// - 活跃文件和序列号分配由独立的互斥锁保护，所有写入经由同一个 Writer 串行追加，
+///   追加完成后才短暂地获取 KeyDir 的写锁发布索引。合并也在这把互斥锁内进行，
+///   只在最后替换文件表和索引时获取写锁，因此读者不会被写入和合并阻塞。
+///
+/// `WriteMod::Sync` 下使用组提交：写者在互斥锁内追加条目并推给内核，释放锁后等待落盘；
+/// 等待者中的一个成为领导者，对活跃文件执行一次 fsync，覆盖此前追加的所有写入后统一唤醒。
+/// 每个写入仍然在落盘后才返回，但多个并发写入只需要一次 fsync。
+#[derive(Clone)]
+pub struct SharedKVStore {
+    inner: Arc<Shared>,
+}
+
+struct Shared {
+    keydir: RwLock<KeyDir>,
+    // 活跃文件，只读模式下为 None；排在目录锁之前，drop 时先落盘再释放锁
+    active: Mutex<Option<ActiveFile>>,
+    // 组提交使用的活跃文件句柄，以及最后一个推给内核、等待 fsync 的序列号
+    sync_file: Option<SyncFile>,
+    pending_sync: AtomicU64,
+    commit: GroupCommit,
+    lock: Option<Box<dyn FileLock>>,
+}
+
+impl SharedKVStore {
+    pub fn new(store: KVStore) -> Result<Self, TitaniumError> {
+        let (keydir, mut active, lock) = store.into_parts();
+        // 只读模式下没有活跃文件，也不会产生需要同步的写入
+        let sync_file = active
+            .as_mut()
+            .map(ActiveFile::enable_group_commit)
+            .transpose()?;
+        Ok(Self {
+            inner: Arc::new(Shared {
+                keydir: RwLock::new(keydir),
+                active: Mutex::new(active),
+                sync_file,
+                pending_sync: AtomicU64::new(0),
+                commit: GroupCommit::default(),
+                lock,
+            }),
+        })
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<LogEntry>, TitaniumError> {
+        self.inner.keydir.read().get(key)
+    }
+
+    pub fn set(&self, key: impl Into<Vec<u8>>, value: Vec<u8>) -> Result<(), TitaniumError> {
+        self.write(|kv| kv.set(key, value))
+    }
+
+    pub fn set_with_ttl(
+        &self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<(), TitaniumError> {
+        self.write(|kv| kv.set_with_ttl(key, value, ttl))
+    }
+
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> Result<(), TitaniumError> {
+        self.write(|kv| kv.remove(key))
+    }
+
+    pub fn compare_and_swap(
+        &self,
+        key: impl Into<Vec<u8>>,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write(|kv| kv.compare_and_swap(key, expected, new))
+    }
+
+    pub fn set_if_absent(
+        &self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write(|kv| kv.set_if_absent(key, value))
+    }
+
+    pub fn set_if_present(
+        &self,
+        key: impl Into<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write(|kv| kv.set_if_present(key, value))
+    }
+
+    pub fn set_if_version(
+        &self,
+        key: impl Into<Vec<u8>>,
+        expected: u64,
+        value: Vec<u8>,
+    ) -> Result<bool, TitaniumError> {
+        self.write(|kv| kv.set_if_version(key, expected, value))
+    }
+
+    pub fn write_batch(&self, batch: WriteBatch) -> Result<(), TitaniumError> {
+        self.write(|kv| kv.write_batch(batch))
+    }
+
+    pub fn stats(&self) -> Result<Stats, TitaniumError> {
+        self.inner.keydir.read().stats()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.keydir.read().snapshot()
+    }
+
+    pub fn get_at(
+        &self,
+        snapshot: &Snapshot,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<LogEntry>, TitaniumError> {
+        self.inner.keydir.read().get_at(snapshot, key)
+    }
+
+    /// 开始一个乐观事务；事务内的读取通过 `read` 传入 KeyDir，例如 `db.read(|kv| txn.get(kv, key))`
+    pub fn begin(&self) -> Transaction {
+        self.inner.keydir.read().begin()
+    }
+
+    /// 提交事务，读集合校验和写入在同一次写者互斥内完成
+    pub fn commit(&self, txn: Transaction) -> Result<(), TitaniumError> {
+        self.write(|kv| kv.commit(txn))
+    }
+
+    pub fn compact(&self) -> Result<(), TitaniumError> {
+        self.write_locked(|kv| kv.compact())
+    }
+
+    /// 手动触发刷盘；只读模式下直接返回
+    pub fn sync(&self) -> Result<(), TitaniumError> {
+        match self.inner.active.lock().as_mut() {
+            Some(active) => active.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// 关闭句柄
+    ///
+    /// 这是最后一个句柄时关闭底层 KVStore (见 `KVStore::close`)；
+    /// 其他句柄仍然存在时只同步已写入的数据，KVStore 在最后一个句柄释放时关闭。
+    pub fn close(self) -> Result<(), TitaniumError> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(shared) => {
+                let Shared { active, lock, .. } = shared;
+                let result = active.into_inner().map_or(Ok(()), ActiveFile::close);
+                // 数据落盘后再释放目录锁
+                drop(lock);
+                result
+            }
+            Err(inner) => SharedKVStore { inner }.sync(),
+        }
+    }
+
+    /// 持有 KeyDir 的读锁执行 `f`，用于扫描、迭代等返回借用迭代器的接口
+    ///
+    /// `f` 执行期间写者仍可以追加数据，但发布索引 (以及合并的最后替换) 需要等待 `f` 结束。
+    pub fn read<T>(&self, f: impl FnOnce(&KeyDir) -> T) -> T {
+        f(&self.inner.keydir.read())
+    }
+
+    /// 持有写者互斥锁执行 `f`，用于需要在一次加锁内完成的多步操作
+    ///
+    /// `f` 执行期间其他写者等待，读者不受影响，能看到 `f` 中已经完成的写入。
+    /// `f` 中的写入在释放锁后通过组提交落盘，落盘完成 (或失败) 后才返回；只读模式下返回 `ReadOnly`。
+    /// 待合并文件过多时，获取锁之前先休眠 (见 `KeyDir::stall_delay`)，只拖慢当前调用者。
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&mut WriteHandle) -> Result<T, TitaniumError>,
+    ) -> Result<T, TitaniumError> {
+        let delay = self.inner.keydir.read().stall_delay();
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+        self.write_locked(f)
+    }
+
+    fn write_locked<T>(
+        &self,
+        f: impl FnOnce(&mut WriteHandle) -> Result<T, TitaniumError>,
+    ) -> Result<T, TitaniumError> {
+        let (result, target) = {
+            let mut active = self.inner.active.lock();
+            let active = active.as_mut().ok_or(TitaniumError::ReadOnly)?;
+            let before = active.pending_sync();
+            let result = f(&mut WriteHandle::shared(active, &self.inner.keydir));
+            let after = active.pending_sync();
+            if after > before {
+                self.inner.pending_sync.store(after, Ordering::Release);
+            }
+            (result, (after > before).then_some(after))
+        };
+        // 即使 f 返回错误，它之前已追加的条目也要等待落盘，避免后续写入越过它们被确认
+        if let Some(seq) = target {
+            self.inner.commit.wait(seq, || self.inner.sync_pending())?;
+        }
+        result
+    }
+}
+
+impl Shared {
+    /// 领导者执行一次 fsync，返回本次覆盖到的序列号
+    fn sync_pending(&self) -> Result<u64, TitaniumError> {
+        // 先取得序列号：此时它之前的写入都已推给内核，且位于当前活跃文件或轮转时已同步的旧文件中
+        let target = self.pending_sync.load(Ordering::Acquire);
+        if let Some(file) = &self.sync_file {
+            file.lock().sync()?;
+        }
+        Ok(target)
+    }
+}
+
+/// 组提交的协调状态
+#[derive(Default)]
+struct GroupCommit {
+    state: Mutex<CommitState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct CommitState {
+    // 已经落盘的最大序列号
+    synced: u64,
+    // 是否有领导者正在执行 fsync
+    syncing: bool,
+}
+
+impl GroupCommit {
+    /// 等待序列号 seq 及之前的写入落盘
+    ///
+    /// 没有 fsync 在进行时，当前线程成为领导者执行 `sync` (返回它覆盖到的序列号)；
+    /// 否则等待正在进行的 fsync 结束，若仍未覆盖 seq 则再次竞争领导者。
+    fn wait(
+        &self,
+        seq: u64,
+        sync: impl FnOnce() -> Result<u64, TitaniumError>,
+    ) -> Result<(), TitaniumError> {
+        let mut state = self.state.lock();
+        loop {
+            if state.synced >= seq {
+                return Ok(());
+            }
+            if state.syncing {
+                self.cond.wait(&mut state);
+                continue;
+            }
+
+            state.syncing = true;
+            let result = MutexGuard::unlocked(&mut state, sync);
+            state.syncing = false;
+            if let Ok(synced) = result {
+                state.synced = state.synced.max(synced);
+            }
+            // 失败时唤醒的等待者会重新竞争领导者并重试
+            self.cond.notify_all();
+            return result.map(|_| ());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::storage::MemFileSystem;
+    use std::sync::atomic::AtomicUsize;
+
+    fn create_shared(path: &str) -> SharedKVStore {
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+        KVStore::new(watcher, Arc::new(MemFileSystem::new()))
+            .unwrap()
+            .into_shared()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shared_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+        assert_send_sync::<SharedKVStore>();
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let db = create_shared("test_shared_concurrent");
+        for i in 0..100 {
+            db.set(format!("key_{}", i), b"0".to_vec()).unwrap();
+        }
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        db.set(format!("w{}_{}", t, i), i.to_string().into_bytes())
+                            .unwrap();
+                        db.set(format!("key_{}", i), t.to_string().into_bytes())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for round in 0..10 {
+                        for i in 0..100 {
+                            // 初始写入的 Key 始终存在，只是值被写者覆盖
+                            let entry = db.get(format!("key_{}", i)).unwrap().unwrap();
+                            assert_eq!(entry.value.len(), 1, "round {}", round);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+        for t in 0..4 {
+            for i in 0..100 {
+                let entry = db.get(format!("w{}_{}", t, i)).unwrap().unwrap();
+                assert_eq!(entry.value, i.to_string().into_bytes());
+            }
+        }
+        assert_eq!(db.read(|kv| kv.keys().count()), 500);
+    }
+
+    #[test]
+    fn test_reader_not_blocked_by_writer() {
+        let db = create_shared("test_shared_reader_progress");
+        db.set("k1", b"v1".to_vec()).unwrap();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let writer = {
+            let db = db.clone();
+            thread::spawn(move || {
+                db.write(|kv| {
+                    kv.set("k2", b"v2".to_vec())?;
+                    started_tx.send(()).unwrap();
+                    // 持有写者互斥锁直到读者完成
+                    release_rx.recv().unwrap();
+                    Ok(())
+                })
+                .unwrap();
+            })
+        };
+        started_rx.recv().unwrap();
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let reader = {
+            let db = db.clone();
+            thread::spawn(move || {
+                let v1 = db.get("k1").unwrap().unwrap().value;
+                // 闭包中已经完成的写入对读者可见
+                let v2 = db.get("k2").unwrap().unwrap().value;
+                done_tx.send((v1, v2)).unwrap();
+            })
+        };
+        let (v1, v2) = done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("reader blocked by an in-progress write");
+        assert_eq!(v1, b"v1");
+        assert_eq!(v2, b"v2");
+
+        release_tx.send(()).unwrap();
+        writer.join().unwrap();
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_write_stall_outside_lock() {
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "test_shared_stall".to_string();
+        cfg.max_file_size = 30;
+        cfg.compaction_threshold = usize::MAX;
+        cfg.write_stall_threshold = 1000;
+        cfg.write_stop_threshold = 1001;
+        watcher.override_config(cfg.clone());
+        let db = KVStore::new(watcher.clone(), Arc::new(MemFileSystem::new()))
+            .unwrap()
+            .into_shared()
+            .unwrap();
+        // 每次写入约 30 字节，几乎每次都会轮转
+        for i in 0..300 {
+            db.set(format!("k{}", i), vec![0u8; 10]).unwrap();
+        }
+        let pending = db.stats().unwrap().pending_compaction_files;
+        assert!(pending >= 200);
+
+        // 降低 stall 阈值后，每次写入前休眠数百毫秒
+        cfg.write_stall_threshold = 1;
+        watcher.override_config(cfg);
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let stalled = {
+            let db = db.clone();
+            thread::spawn(move || {
+                db.set("stalled", b"v".to_vec()).unwrap();
+                done_tx.send(()).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+
+        // 休眠发生在获取写锁之前，其他需要写锁的操作和读取不受影响
+        db.sync().unwrap();
+        assert!(db.get("k0").unwrap().is_some());
+        assert!(done_rx.try_recv().is_err(), "stalled writer finished early");
+
+        stalled.join().unwrap();
+        assert!(db.get("stalled").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_shared_transaction() {
+        let db = create_shared("test_shared_txn");
+        db.set("counter", b"1".to_vec()).unwrap();
+
+        let mut txn = db.begin();
+        let value = db.read(|kv| txn.get(kv, "counter")).unwrap().unwrap();
+        assert_eq!(value, b"1");
+        txn.set("counter", b"2".to_vec());
+        db.commit(txn).unwrap();
+        assert_eq!(db.get("counter").unwrap().unwrap().value, b"2");
+    }
+
+    #[test]
+    fn test_shared_close() {
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = "test_shared_close".to_string();
+        watcher.override_config(cfg);
+        let fs = Arc::new(MemFileSystem::new());
+
+        let db = KVStore::new(watcher.clone(), fs.clone())
+            .unwrap()
+            .into_shared()
+            .unwrap();
+        let other = db.clone();
+        db.set("k", b"v".to_vec()).unwrap();
+
+        // 仍有其他句柄时，KVStore 保持打开
+        db.close().unwrap();
+        assert_eq!(other.get("k").unwrap().unwrap().value, b"v");
+        assert!(KVStore::new(watcher.clone(), fs.clone()).is_err());
+
+        // 最后一个句柄关闭后释放目录锁
+        other.close().unwrap();
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"v");
+    }
+
+    #[test]
+    fn test_group_commit_batches_fsync() {
+        let commit = Arc::new(GroupCommit::default());
+        let written = Arc::new(AtomicU64::new(0));
+        let syncs = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (commit, written, syncs) = (commit.clone(), written.clone(), syncs.clone());
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        let seq = written.fetch_add(1, Ordering::SeqCst) + 1;
+                        commit
+                            .wait(seq, || {
+                                let target = written.load(Ordering::SeqCst);
+                                syncs.fetch_add(1, Ordering::SeqCst);
+                                thread::sleep(Duration::from_millis(2));
+                                Ok(target)
+                            })
+                            .unwrap();
+                        assert!(commit.state.lock().synced >= seq);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // 并发写者共享 fsync，次数明显少于写入数
+        assert!(syncs.load(Ordering::SeqCst) < 160);
+    }
+
+    #[test]
+    fn test_group_commit_error_is_retried() {
+        let commit = GroupCommit::default();
+        let err = commit.wait(1, || Err(TitaniumError::SystemOverload));
+        assert!(matches!(err, Err(TitaniumError::SystemOverload)));
+        // 失败不会推进已落盘的序列号，下一个写者重新执行 fsync
+        commit.wait(1, || Ok(1)).unwrap();
+        commit.wait(1, || panic!("already synced")).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use crate::index::LogIndex;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// 活跃快照登记表：序列号 -> 引用计数
+///
+/// 由 KVStore 和它创建的所有 Snapshot 共享，Snapshot 被 drop 时自动注销。
+#[derive(Clone, Default)]
+pub(crate) struct SnapshotList {
+    inner: Arc<Mutex<BTreeMap<u64, usize>>>,
+}
+
+impl SnapshotList {
+    pub(crate) fn acquire(&self, seq: u64) -> Snapshot {
+        *self
+            .inner
+            .lock()
+            .expect("Snapshot list poisoned")
+            .entry(seq)
+            .or_insert(0) += 1;
+        Snapshot {
+            seq,
+            list: self.clone(),
+        }
+    }
+
+    fn release(&self, seq: u64) {
+        let mut guard = self.inner.lock().expect("Snapshot list poisoned");
+        if let Some(count) = guard.get_mut(&seq) {
+            *count -= 1;
+            if *count == 0 {
+                guard.remove(&seq);
+            }
+        }
+    }
+
+    /// 快照是否由这个登记表 (即同一个 KVStore) 创建
+    pub(crate) fn owns(&self, snapshot: &Snapshot) -> bool {
+        Arc::ptr_eq(&self.inner, &snapshot.list.inner)
+    }
+
+    /// 最旧的活跃快照序列号，没有活跃快照时返回 None
+    pub(crate) fn oldest(&self) -> Option<u64> {
+        self.inner
+            .lock()
+            .expect("Snapshot list poisoned")
+            .keys()
+            .next()
+            .copied()
+    }
+}
+
+/// 一致性读快照
+///
+/// 固定创建时的序列号：通过 `KVStore::get_at` / `KVStore::iter_at` 读取时，
+/// 只能看到序列号不大于它的写入，之后的写入、删除和合并都不会影响快照的视图。
+/// 快照存活期间，被覆盖或删除的旧版本会保留在内存的版本历史和磁盘上，因此应尽快释放 (drop)。
+pub struct Snapshot {
+    seq: u64,
+    list: SnapshotList,
+}
+
+impl Snapshot {
+    pub fn sequence_number(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl Clone for Snapshot {
+    fn clone(&self) -> Self {
+        self.list.acquire(self.seq)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.list.release(self.seq);
+    }
+}
+
+/// 被覆盖或删除的旧版本，只在存在活跃快照时记录
+///
+/// 每条记录为 (superseded_at, prev)：序列号为 superseded_at 的写入覆盖了版本 prev
+/// (prev 为 None 表示写入前 Key 不存在)。同一个 Key 的记录按 superseded_at 升序排列。
+///
+/// 对于快照 S，第一个 superseded_at > S 的记录中的 prev 就是 S 能看到的版本；
+/// 没有这样的记录时，说明 S 之后没有写过该 Key，当前索引即为 S 的视图。
+#[derive(Default)]
+pub(crate) struct VersionHistory {
+    versions: HashMap<Vec<u8>, Vec<(u64, Option<LogIndex>)>>,
+    // 上次清理时最旧的活跃快照，用于避免每次写入都扫描全部记录
+    floor: Option<u64>,
+}
+
+impl VersionHistory {
+    pub(crate) fn record(&mut self, key: &[u8], superseded_at: u64, prev: Option<LogIndex>) {
+        match self.versions.get_mut(key) {
+            Some(records) => records.push((superseded_at, prev)),
+            None => {
+                self.versions
+                    .insert(key.to_vec(), vec![(superseded_at, prev)]);
+            }
+        }
+    }
+
+    /// 查找快照 seq 可见的版本；返回 None 表示应使用当前索引
+    pub(crate) fn lookup(&self, key: &[u8], seq: u64) -> Option<Option<LogIndex>> {
+        self.versions
+            .get(key)?
+            .iter()
+            .find(|(superseded_at, _)| *superseded_at > seq)
+            .map(|(_, prev)| *prev)
+    }
+
+    /// 丢弃任何活跃快照都不再需要的记录 (superseded_at <= 最旧快照)
+    pub(crate) fn prune(&mut self, oldest: Option<u64>) {
+        if oldest == self.floor {
+            return;
+        }
+        self.floor = oldest;
+        match oldest {
+            None => self.versions.clear(),
+            Some(oldest) => self.versions.retain(|_, records| {
+                records.retain(|(superseded_at, _)| *superseded_at > oldest);
+                !records.is_empty()
+            }),
+        }
+    }
+
+    pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
+        self.versions.contains_key(key)
+    }
+
+    /// 遍历所有记录中引用的旧版本位置
+    pub(crate) fn locations(&self) -> impl Iterator<Item = LogIndex> + '_ {
+        self.versions
+            .values()
+            .flat_map(|records| records.iter().filter_map(|(_, prev)| *prev))
+    }
+
+    /// 有版本历史的 Key
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.versions.keys().map(|k| k.as_slice())
+    }
+
+    /// 合并后更新旧版本的位置；`f` 返回 None 表示该位置没有变化
+    pub(crate) fn relocate(&mut self, mut f: impl FnMut(LogIndex) -> Option<Option<LogIndex>>) {
+        for records in self.versions.values_mut() {
+            for (_, prev) in records.iter_mut() {
+                if let Some(index) = *prev
+                    && let Some(moved) = f(index)
+                {
+                    *prev = moved;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_list() {
+        let list = SnapshotList::default();
+        assert_eq!(list.oldest(), None);
+
+        let s1 = list.acquire(5);
+        let s2 = list.acquire(9);
+        let s3 = s1.clone();
+        assert_eq!(list.oldest(), Some(5));
+
+        drop(s1);
+        assert_eq!(list.oldest(), Some(5));
+        drop(s3);
+        assert_eq!(list.oldest(), Some(9));
+        drop(s2);
+        assert_eq!(list.oldest(), None);
+
+        let other = SnapshotList::default();
+        let s4 = list.acquire(1);
+        assert!(list.owns(&s4));
+        assert!(!other.owns(&s4));
+    }
+
+    #[test]
+    fn test_version_history() {
+        let v1 = LogIndex::new(1, 10, 1);
+        let v2 = LogIndex::new(1, 20, 1);
+        let mut history = VersionHistory::default();
+        // seq 1 写入 v1，seq 5 覆盖为 v2，seq 8 删除
+        history.record(b"k", 5, Some(v1));
+        history.record(b"k", 8, Some(v2));
+
+        assert_eq!(history.lookup(b"k", 3), Some(Some(v1)));
+        assert_eq!(history.lookup(b"k", 5), Some(Some(v2)));
+        assert_eq!(history.lookup(b"k", 8), None);
+        assert_eq!(history.lookup(b"other", 3), None);
+
+        // seq 0 的快照之后写入的 Key，快照中不可见
+        history.record(b"new", 2, None);
+        assert_eq!(history.lookup(b"new", 0), Some(None));
+
+        history.prune(Some(5));
+        assert_eq!(history.lookup(b"k", 5), Some(Some(v2)));
+        assert!(!history.contains_key(b"new"));
+
+        history.relocate(|index| (index == v2).then_some(Some(v1)));
+        assert_eq!(history.lookup(b"k", 5), Some(Some(v1)));
+
+        history.prune(None);
+        assert!(!history.contains_key(b"k"));
+    }
+}
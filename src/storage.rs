@@ -1,5 +1,3 @@
-use std::io::{self, Read, Seek};
-
 mod memory;
 mod os;
 mod traits;
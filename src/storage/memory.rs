@@ -1,10 +1,13 @@
-use super::traits::{FileMetadata, FileSystem, RandomAccessFile, Storage, WritableFile};
-use parking_lot::RwLock;
+use super::traits::{
+    FileLock, FileMetadata, FileSystem, LockMode, RandomAccessFile, Storage, WritableFile,
+};
+use parking_lot::{Mutex, RwLock};
 use std::cmp;
 use std::collections::HashMap;
 use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // 为 Cursor 实现 RandomAccessFile
 impl RandomAccessFile for io::Cursor<Vec<u8>> {
@@ -41,18 +44,60 @@ impl Storage for io::Cursor<Vec<u8>> {}
 
 // --- In-Memory File System (For Testing) ---
 
+type MemFileData = Arc<RwLock<Vec<u8>>>;
+
 #[derive(Clone)]
 pub struct MemFileSystem {
     // Path -> File Content
-    files: Arc<RwLock<HashMap<PathBuf, Arc<RwLock<Vec<u8>>>>>>,
+    files: Arc<RwLock<HashMap<PathBuf, MemFileData>>>,
+    // 模拟的磁盘容量，默认无限制
+    capacity: Arc<AtomicU64>,
+    // 模拟的文件锁：路径 -> 持有者数量 (独占锁记为 None)
+    locks: MemLocks,
 }
 
 impl MemFileSystem {
     pub fn new() -> Self {
         Self {
             files: Arc::new(RwLock::new(HashMap::new())),
+            capacity: Arc::new(AtomicU64::new(u64::MAX)),
+            locks: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// 设置模拟的磁盘容量，可用空间 = 容量 - 所有文件的总大小。用于测试磁盘写满场景。
+    pub fn set_capacity(&self, capacity: u64) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+}
+
+impl Default for MemFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type MemLocks = Arc<Mutex<HashMap<PathBuf, Option<usize>>>>;
+
+/// 模拟的文件锁，drop 时释放一个持有者
+struct MemFileLock {
+    path: PathBuf,
+    locks: MemLocks,
+}
+
+impl FileLock for MemFileLock {}
+
+impl Drop for MemFileLock {
+    fn drop(&mut self) {
+        let mut guard = self.locks.lock();
+        if let Some(Some(count)) = guard.get_mut(&self.path)
+            && *count > 1
+        {
+            *count -= 1;
+            return;
+        }
+        guard.remove(&self.path);
+    }
 }
 
 struct MemFile {
@@ -197,4 +242,43 @@ impl FileSystem for MemFileSystem {
             is_file: true,
         })
     }
+
+    fn available_space(&self, _path: &Path) -> io::Result<u64> {
+        let used: u64 = self
+            .files
+            .read()
+            .values()
+            .map(|data| data.read().len() as u64)
+            .sum();
+        Ok(self.capacity.load(Ordering::Relaxed).saturating_sub(used))
+    }
+    fn lock_file(&self, path: &Path, mode: LockMode) -> io::Result<Box<dyn FileLock>> {
+        {
+            let mut guard = self.locks.lock();
+            match (guard.get_mut(path), mode) {
+                (None, LockMode::Shared) => {
+                    guard.insert(path.to_path_buf(), Some(1));
+                }
+                (None, LockMode::Exclusive) => {
+                    guard.insert(path.to_path_buf(), None);
+                }
+                (Some(Some(count)), LockMode::Shared) => *count += 1,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "File is locked by another handle",
+                    ));
+                }
+            }
+        }
+        // 与 OS 实现一致：锁文件不存在时创建
+        self.files
+            .write()
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Arc::new(RwLock::new(Vec::new())));
+        Ok(Box::new(MemFileLock {
+            path: path.to_path_buf(),
+            locks: self.locks.clone(),
+        }))
+    }
 }
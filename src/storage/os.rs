@@ -1,4 +1,6 @@
-use super::traits::{FileMetadata, FileSystem, RandomAccessFile, Storage, WritableFile};
+use super::traits::{
+    FileLock, FileMetadata, FileSystem, LockMode, RandomAccessFile, Storage, WritableFile,
+};
 use std::fs::File;
 use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
@@ -9,16 +11,96 @@ cfg_if::cfg_if! {
         fn read_at_impl(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
             FileExt::read_at(file, buf, offset)
         }
+
+        fn available_space_impl(path: &Path) -> io::Result<u64> {
+            use std::ffi::CString;
+            use std::os::unix::ffi::OsStrExt;
+
+            let c_path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+            // SAFETY: c_path 是合法的 NUL 结尾字符串，stat 指向有效的栈内存
+            if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // f_bavail: 非特权用户可用的块数
+            Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+        }
+
+        fn try_lock_impl(file: &File, mode: LockMode) -> io::Result<()> {
+            use std::os::unix::io::AsRawFd;
+
+            let operation = match mode {
+                LockMode::Shared => libc::LOCK_SH,
+                LockMode::Exclusive => libc::LOCK_EX,
+            };
+            // SAFETY: fd 在 file 的生命周期内有效；锁随 fd 关闭自动释放
+            if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } != 0 {
+                // 锁已被持有时为 EWOULDBLOCK，对应 ErrorKind::WouldBlock
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
     } else if #[cfg(windows)] {
         use std::os::windows::fs::FileExt;
         fn read_at_impl(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
             FileExt::seek_read(file, buf, offset)
         }
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn GetDiskFreeSpaceExW(
+                directory: *const u16,
+                free_bytes_available_to_caller: *mut u64,
+                total_bytes: *mut u64,
+                total_free_bytes: *mut u64,
+            ) -> i32;
+        }
+
+        fn available_space_impl(path: &Path) -> io::Result<u64> {
+            use std::os::windows::ffi::OsStrExt;
+
+            let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+            let mut available = 0u64;
+            // SAFETY: wide 是 NUL 结尾的 UTF-16 路径，不需要的输出参数传空指针
+            let ok = unsafe {
+                GetDiskFreeSpaceExW(
+                    wide.as_ptr(),
+                    &mut available,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // 当前用户可用的字节数 (考虑磁盘配额)，与 unix 的 f_bavail 对应
+            Ok(available)
+        }
+
+        fn try_lock_impl(file: &File, mode: LockMode) -> io::Result<()> {
+            let result = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+            result.map_err(|e| match e {
+                std::fs::TryLockError::WouldBlock => io::ErrorKind::WouldBlock.into(),
+                std::fs::TryLockError::Error(e) => e,
+            })
+        }
     } else {
         // 兜底逻辑：在不支持的平台上也能编译通过，但运行时返回错误
         fn read_at_impl(_file: &File, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
             Err(io::Error::new(io::ErrorKind::Unsupported, "Platform not supported"))
         }
+
+        fn available_space_impl(_path: &Path) -> io::Result<u64> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "Platform not supported"))
+        }
+
+        fn try_lock_impl(_file: &File, _mode: LockMode) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "Platform not supported"))
+        }
     }
 }
 
@@ -73,6 +155,13 @@ impl Read for OsFile {
 
 impl Storage for OsFile {}
 
+/// 持有建议锁的文件，drop 时关闭文件即释放锁
+pub struct OsFileLock {
+    _file: File,
+}
+
+impl FileLock for OsFileLock {}
+
 /// 默认的 OS 文件系统实现
 pub struct OsFileSystem;
 
@@ -137,4 +226,27 @@ impl FileSystem for OsFileSystem {
             is_file: meta.is_file(),
         })
     }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        available_space_impl(path)
+    }
+
+    fn lock_file(&self, path: &Path, mode: LockMode) -> io::Result<Box<dyn FileLock>> {
+        // 共享锁优先只读打开已有的锁文件，以便在只读挂载上使用
+        let file = match mode {
+            LockMode::Shared => std::fs::File::open(path),
+            LockMode::Exclusive => Err(io::ErrorKind::NotFound.into()),
+        };
+        let file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => std::fs::File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?,
+            Err(e) => return Err(e),
+        };
+        try_lock_impl(&file, mode)?;
+        Ok(Box::new(OsFileLock { _file: file }))
+    }
 }
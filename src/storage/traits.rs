@@ -7,6 +7,7 @@ use std::path::{Path, PathBuf};
 /// 核心特性：
 /// 1. `read_at` 是无状态的（不改变文件游标），支持多线程并发读取。
 /// 2. 类似于 Unix 的 `pread` 或 Windows 的 `ReadFile` (Overlapped)。
+#[allow(clippy::len_without_is_empty)]
 pub trait RandomAccessFile: Send + Sync {
     fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
     fn len(&self) -> io::Result<u64>;
@@ -65,6 +66,19 @@ impl RandomAccessFile for Box<dyn RandomAccessFile> {
     }
 }
 
+/// 文件锁的模式：共享锁之间互相兼容，独占锁与任何锁都互斥
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// [Guard Trait] 文件锁
+///
+/// 由 `FileSystem::lock_file` 返回，持有期间其他进程 (或同一进程内的其他 KVStore) 无法获得同一把锁，
+/// drop 时释放。
+pub trait FileLock: Send + Sync {}
+
 /// [Factory Trait] 文件系统抽象
 ///
 /// 职责：负责文件的生命周期管理 (CRUD) 和 路径解析。
@@ -82,6 +96,10 @@ pub trait FileSystem: Send + Sync {
     fn create_dir_all(&self, path: &Path) -> io::Result<()>;
     fn list_files(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
     fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+    /// 查询 path 所在文件系统的可用空间 (字节)
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
+    /// 以非阻塞方式对 path 加建议锁 (文件不存在时创建)，与已持有的锁冲突时返回 `WouldBlock`
+    fn lock_file(&self, path: &Path, mode: LockMode) -> io::Result<Box<dyn FileLock>>;
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,158 @@
+use crate::config::{ConfigWatcher, DEFAULT_SYNC_INTERVAL_MS, WriteMod};
+use crate::storage::Storage;
+use parking_lot::{Condvar, Mutex};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 活跃文件的独立同步句柄，轮转时由 KVStore 原地替换为新的活跃文件
+pub(crate) type SyncFile = Arc<Mutex<Box<dyn Storage>>>;
+
+/// `WriteMod::Interval` 的后台同步线程
+///
+/// 每个周期对活跃文件执行一次 fsync；写入路径只负责把数据推给内核。
+/// 周期在每轮开始时从配置读取，热加载切换到其他写入模式后线程空转，不再同步。
+/// drop 时通知线程退出并等待其结束。
+pub(crate) struct BackgroundSyncer {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundSyncer {
+    pub(crate) fn spawn(config: ConfigWatcher, file: SyncFile) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            let (lock, cond) = &*thread_stop;
+            loop {
+                let mode = config.write_mod();
+                let interval = match mode {
+                    WriteMod::Interval(ms) => ms,
+                    _ => DEFAULT_SYNC_INTERVAL_MS,
+                };
+                {
+                    let mut stopped = lock.lock();
+                    if !*stopped {
+                        cond.wait_for(&mut stopped, Duration::from_millis(interval));
+                    }
+                    if *stopped {
+                        break;
+                    }
+                }
+                if let WriteMod::Interval(_) = mode
+                    && let Err(e) = file.lock().sync()
+                {
+                    eprintln!("Background sync failed: {}", e);
+                }
+            }
+        });
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for BackgroundSyncer {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.stop;
+        *lock.lock() = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{RandomAccessFile, WritableFile};
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 只统计 sync 次数的文件
+    struct CountingFile {
+        syncs: Arc<AtomicUsize>,
+    }
+
+    impl RandomAccessFile for CountingFile {
+        fn read_at(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+            Ok(0)
+        }
+        fn len(&self) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    impl WritableFile for CountingFile {
+        fn sync(&mut self) -> io::Result<()> {
+            self.syncs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        fn set_len(&self, _len: u64) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Write for CountingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Seek for CountingFile {
+        fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    impl io::Read for CountingFile {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Storage for CountingFile {}
+
+    fn watcher_with(write_mod: WriteMod) -> ConfigWatcher {
+        let watcher = ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.write_mod = write_mod;
+        watcher.override_config(cfg);
+        watcher
+    }
+
+    #[test]
+    fn test_background_sync() {
+        let syncs = Arc::new(AtomicUsize::new(0));
+        let file: SyncFile = Arc::new(Mutex::new(Box::new(CountingFile {
+            syncs: syncs.clone(),
+        })));
+
+        let syncer = BackgroundSyncer::spawn(watcher_with(WriteMod::Interval(5)), file);
+        thread::sleep(Duration::from_millis(100));
+        // drop 会立即唤醒线程并等待退出，之后不再同步
+        drop(syncer);
+        let count = syncs.load(Ordering::SeqCst);
+        assert!(count >= 2, "expected periodic syncs, got {}", count);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(syncs.load(Ordering::SeqCst), count);
+    }
+
+    #[test]
+    fn test_background_sync_idle_in_other_modes() {
+        let syncs = Arc::new(AtomicUsize::new(0));
+        let file: SyncFile = Arc::new(Mutex::new(Box::new(CountingFile {
+            syncs: syncs.clone(),
+        })));
+
+        let syncer = BackgroundSyncer::spawn(watcher_with(WriteMod::Buffer), file);
+        thread::sleep(Duration::from_millis(20));
+        drop(syncer);
+        assert_eq!(syncs.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::batch::WriteBatch;
+use crate::error::TitaniumError;
+use crate::kv::KeyDir;
+use crate::snapshot::Snapshot;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// 乐观事务
+///
+/// 通过 `KVStore::begin` 创建。读取基于开始时的快照，并记录读到的版本 (序列号)；
+/// 写入只缓存在事务中。`KVStore::commit` 时校验所有读过的 Key 在此期间没有被修改，
+/// 通过后把缓存的写入作为一个 WriteBatch 原子地提交，否则返回 `TransactionConflict`。
+///
+/// 事务不借用 KVStore，读取时需要传入；事务存活期间会持有一个快照。
+/// 事务只能在创建它的 KVStore 上读取和提交，否则返回 `TransactionStoreMismatch`。
+pub struct Transaction {
+    snapshot: Snapshot,
+    // 读过的 Key -> 读到的版本序列号 (None 表示不存在)
+    reads: HashMap<Vec<u8>, Option<u64>>,
+    writes: WriteBatch,
+    // 事务内写入的最新值 (None 表示删除)，保证读到自己的写入
+    pending: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Transaction {
+    pub(crate) fn new(snapshot: Snapshot) -> Self {
+        Self {
+            snapshot,
+            reads: HashMap::new(),
+            writes: WriteBatch::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// 读取 Key 的值：优先返回事务内的写入，否则读取开始时的快照并记录版本
+    pub fn get(
+        &mut self,
+        kv: &KeyDir,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, TitaniumError> {
+        self.check_store(kv)?;
+        let key = key.as_ref();
+        if let Some(value) = self.pending.get(key) {
+            return Ok(value.clone());
+        }
+
+        let entry = match kv.index_at(self.snapshot.sequence_number(), key) {
+            Some(index) => kv.read_entry(index)?,
+            None => None,
+        };
+        self.reads
+            .entry(key.to_vec())
+            .or_insert(entry.as_ref().map(|e| e.sequence_number));
+        Ok(entry.map(|e| e.value))
+    }
+
+    pub fn set(&mut self, key: impl Into<Vec<u8>>, value: Vec<u8>) {
+        let key = key.into();
+        self.pending.insert(key.clone(), Some(value.clone()));
+        self.writes.put(key, value);
+    }
+
+    pub fn set_with_ttl(&mut self, key: impl Into<Vec<u8>>, value: Vec<u8>, ttl: Duration) {
+        let key = key.into();
+        self.pending.insert(key.clone(), Some(value.clone()));
+        self.writes.put_with_ttl(key, value, ttl);
+    }
+
+    pub fn remove(&mut self, key: impl Into<Vec<u8>>) {
+        let key = key.into();
+        self.pending.insert(key.clone(), None);
+        self.writes.delete(key);
+    }
+
+    /// 校验读集合，通过后返回需要提交的写入，见 `KVStore::commit`
+    ///
+    /// 任何读过的 Key 在事务开始后被其他写入修改 (包括删除、过期) 时返回 `TransactionConflict`。
+    pub(crate) fn validate(self, kv: &KeyDir) -> Result<WriteBatch, TitaniumError> {
+        self.check_store(kv)?;
+        for (key, seen) in &self.reads {
+            if kv.current_version(key)? != *seen {
+                return Err(TitaniumError::TransactionConflict { key: key.clone() });
+            }
+        }
+        Ok(self.writes)
+    }
+
+    /// 事务的快照记录了创建它的 KVStore，在其他 KVStore 上读取或提交会得到错误的版本
+    fn check_store(&self, kv: &KeyDir) -> Result<(), TitaniumError> {
+        if !kv.snapshots.owns(&self.snapshot) {
+            return Err(TitaniumError::TransactionStoreMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::kv::KVStore;
+    use crate::storage::MemFileSystem;
+    use std::sync::Arc;
+
+    fn create_kv_store(path: &str) -> KVStore {
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+        KVStore::new(watcher, Arc::new(MemFileSystem::new())).unwrap()
+    }
+
+    fn balance(kv: &KVStore, key: &str) -> u64 {
+        let value = kv.get(key).unwrap().unwrap().value;
+        String::from_utf8(value).unwrap().parse().unwrap()
+    }
+
+    /// 在事务中从 from 转账 amount 到 to
+    fn transfer(txn: &mut Transaction, kv: &KVStore, from: &str, to: &str, amount: u64) {
+        let read = |txn: &mut Transaction, key: &str| -> u64 {
+            let value = txn.get(kv, key).unwrap().unwrap();
+            String::from_utf8(value).unwrap().parse().unwrap()
+        };
+        let from_balance = read(txn, from);
+        let to_balance = read(txn, to);
+        txn.set(from, (from_balance - amount).to_string().into_bytes());
+        txn.set(to, (to_balance + amount).to_string().into_bytes());
+    }
+
+    #[test]
+    fn test_transaction_commit() {
+        let mut kv = create_kv_store("test_txn_commit");
+        kv.set("alice", b"100".to_vec()).unwrap();
+        kv.set("bob", b"0".to_vec()).unwrap();
+
+        let mut txn = kv.begin();
+        transfer(&mut txn, &kv, "alice", "bob", 30);
+        // 读到自己的写入
+        assert_eq!(txn.get(&kv, "alice").unwrap().unwrap(), b"70");
+        txn.remove("tmp");
+        assert!(txn.get(&kv, "tmp").unwrap().is_none());
+        // 提交前其他读者看不到事务中的写入
+        assert_eq!(balance(&kv, "alice"), 100);
+
+        kv.commit(txn).unwrap();
+        assert_eq!(balance(&kv, "alice"), 70);
+        assert_eq!(balance(&kv, "bob"), 30);
+    }
+
+    #[test]
+    fn test_transaction_conflict() {
+        let mut kv = create_kv_store("test_txn_conflict");
+        kv.set("alice", b"100".to_vec()).unwrap();
+        kv.set("bob", b"0".to_vec()).unwrap();
+
+        let mut t1 = kv.begin();
+        let mut t2 = kv.begin();
+        transfer(&mut t1, &kv, "alice", "bob", 30);
+        transfer(&mut t2, &kv, "alice", "bob", 50);
+
+        kv.commit(t1).unwrap();
+        match kv.commit(t2) {
+            Err(TitaniumError::TransactionConflict { .. }) => (),
+            other => panic!("Expected TransactionConflict, got {:?}", other),
+        }
+        assert_eq!(balance(&kv, "alice"), 70);
+        assert_eq!(balance(&kv, "bob"), 30);
+
+        // 读到 "不存在" 之后 Key 被创建，同样视为冲突
+        let mut txn = kv.begin();
+        assert!(txn.get(&kv, "carol").unwrap().is_none());
+        txn.set("carol", b"1".to_vec());
+        kv.set("carol", b"0".to_vec()).unwrap();
+        assert!(matches!(
+            kv.commit(txn),
+            Err(TitaniumError::TransactionConflict { key }) if key == b"carol"
+        ));
+    }
+
+    #[test]
+    fn test_transaction_store_mismatch() {
+        let mut kv = create_kv_store("test_txn_mismatch");
+        let mut other = create_kv_store("test_txn_mismatch_other");
+        kv.set("k", b"1".to_vec()).unwrap();
+
+        let mut txn = kv.begin();
+        assert!(matches!(
+            txn.get(&other, "k"),
+            Err(TitaniumError::TransactionStoreMismatch)
+        ));
+        txn.set("k", b"2".to_vec());
+        assert!(matches!(
+            other.commit(txn),
+            Err(TitaniumError::TransactionStoreMismatch)
+        ));
+        assert!(other.get("k").unwrap().is_none());
+        assert_eq!(kv.get("k").unwrap().unwrap().value, b"1");
+    }
+}
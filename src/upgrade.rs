@@ -0,0 +1,227 @@
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::TitaniumError,
+    hint,
+    kv::{FileAtReader, KVStore},
+    log_entry::{CURRENT_FORMAT_VERSION, Decoder, FileHeader},
+    storage::{FileSystem, LockMode},
+    writer::Writer,
+};
+
+/// 一次升级的结果统计
+#[derive(Debug, Default, PartialEq)]
+pub struct UpgradeReport {
+    /// 被重写为当前格式的文件 ID
+    pub upgraded: Vec<u32>,
+    /// 已经是当前格式、无需处理的文件 ID
+    pub up_to_date: Vec<u32>,
+    /// 无法识别的外部文件 ID (保持不动)
+    pub foreign: Vec<u32>,
+    /// 重写的条目总数
+    pub entries: u64,
+}
+
+/// 离线数据目录升级工具
+///
+/// 将旧格式的 .bs 文件用对应版本的 Decoder 读出，再以当前格式重写。
+/// 必须在没有 KVStore 打开该目录时运行 (运行期间持有目录锁，目录已被打开时返回 `DirectoryLocked`)。
+///
+/// 每个文件独立升级：先写入 `NNNN.bs.tmp`，sync 后通过 `FileSystem::rename` 原子替换原文件。
+/// 中途中断时，已升级的文件会被识别为当前格式而跳过，未完成的临时文件会在下次运行时被丢弃，
+/// 因此重复执行即可从中断处继续。
+pub struct Upgrader;
+
+impl Upgrader {
+    pub fn upgrade(
+        fs: &dyn FileSystem,
+        data_path: &Path,
+        max_key_size: usize,
+        max_val_size: usize,
+    ) -> Result<UpgradeReport, TitaniumError> {
+        // 升级期间持有目录锁，拒绝与打开该目录的 KVStore 同时运行
+        let _lock = KVStore::lock_dir(fs, data_path, LockMode::Exclusive)?;
+        let mut report = UpgradeReport::default();
+        let mut decoder = Decoder::new(max_key_size, max_val_size);
+
+        for file_id in KVStore::list_data_file_ids(fs, data_path)? {
+            let path = data_path.join(format!("{:04}.bs", file_id));
+            let reader = fs.open_reader(&path)?;
+            let version = match KVStore::probe_version(reader.as_ref(), &mut decoder)? {
+                Some(version) => version,
+                None => {
+                    eprintln!("Upgrade: Skipping foreign file {:?}.", path);
+                    report.foreign.push(file_id);
+                    continue;
+                }
+            };
+            if version == CURRENT_FORMAT_VERSION {
+                report.up_to_date.push(file_id);
+                continue;
+            }
+
+            drop(reader);
+            let entries = Self::upgrade_file(fs, data_path, file_id, version, &mut decoder)?;
+            println!(
+                "Upgrade: Rewrote {:?} from version {} to {} ({} entries).",
+                path, version, CURRENT_FORMAT_VERSION, entries
+            );
+            report.upgraded.push(file_id);
+            report.entries += entries;
+        }
+
+        Ok(report)
+    }
+
+    fn upgrade_file(
+        fs: &dyn FileSystem,
+        data_path: &Path,
+        file_id: u32,
+        version: u8,
+        decoder: &mut Decoder,
+    ) -> Result<u64, TitaniumError> {
+        let path = data_path.join(format!("{:04}.bs", file_id));
+        let tmp_path = upgrade_tmp_path(data_path, file_id);
+        // 上一次中断遗留的临时文件
+        if fs.exists(&tmp_path) {
+            fs.remove_file(&tmp_path)?;
+        }
+
+        let reader = fs.open_reader(&path)?;
+        decoder.set_version(version);
+        let mut reader = BufReader::new(FileAtReader {
+            reader: reader.as_ref(),
+            offset: FileHeader::data_offset(version),
+        });
+        let mut writer = Writer::create(fs.create_file(&tmp_path)?)?;
+
+        let mut entries = 0;
+        loop {
+            match decoder.decode_from(&mut reader) {
+                Ok(Some(entry)) => {
+                    writer.write_entry(&entry)?;
+                    entries += 1;
+                }
+                Ok(None) => break,
+                // 与 restore 一致：损坏的尾部被丢弃
+                Err(e) if e.is_corruption() => {
+                    eprintln!(
+                        "Upgrade: Corrupted data in file {} after {} entries ({}). Dropping the tail.",
+                        file_id, entries, e
+                    );
+                    break;
+                }
+                Err(e) => {
+                    let _ = fs.remove_file(&tmp_path);
+                    return Err(e);
+                }
+            }
+        }
+        writer.sync()?;
+        drop(writer);
+
+        // 旧 Hint 文件中的偏移量已失效，必须在替换数据文件之前删除
+        let hint_path = hint::hint_path(data_path, file_id);
+        if fs.exists(&hint_path) {
+            fs.remove_file(&hint_path)?;
+        }
+        fs.rename(&tmp_path, &path)?;
+        Ok(entries)
+    }
+}
+
+fn upgrade_tmp_path(data_path: &Path, file_id: u32) -> PathBuf {
+    data_path.join(format!("{:04}.bs.tmp", file_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::log_entry::{LEGACY_FORMAT_VERSION, LogEntry};
+    use crate::storage::MemFileSystem;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    fn write_legacy_file(fs: &MemFileSystem, dir: &Path, file_id: u32, entries: &[LogEntry]) {
+        let mut file = fs
+            .create_file(&dir.join(format!("{:04}.bs", file_id)))
+            .unwrap();
+        for entry in entries {
+            entry.encode_to(&mut file).unwrap();
+        }
+    }
+
+    fn file_version(fs: &MemFileSystem, dir: &Path, file_id: u32) -> Option<u8> {
+        let reader = fs
+            .open_reader(&dir.join(format!("{:04}.bs", file_id)))
+            .unwrap();
+        let mut decoder = Decoder::new(1024, 1024);
+        KVStore::probe_version(reader.as_ref(), &mut decoder).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_legacy_files() {
+        let path = "test_upgrade";
+        let dir = Path::new(path);
+        let fs = Arc::new(MemFileSystem::new());
+
+        write_legacy_file(
+            &fs,
+            dir,
+            1,
+            &[
+                LogEntry::new("k1".to_string(), b"v1".to_vec(), 1).build(),
+                LogEntry::new("k2".to_string(), b"v2".to_vec(), 2).build(),
+            ],
+        );
+        write_legacy_file(
+            &fs,
+            dir,
+            2,
+            &[
+                LogEntry::new_tombstone("k1".to_string(), 3),
+                LogEntry::new("k3".to_string(), b"v3".to_vec(), 4)
+                    .with_ttl(u64::MAX)
+                    .build(),
+            ],
+        );
+        // 外部文件保持不动
+        fs.create_file(&dir.join("0003.bs"))
+            .unwrap()
+            .write_all(b"foreign data here")
+            .unwrap();
+        // 上一次中断遗留的临时文件
+        fs.create_file(&upgrade_tmp_path(dir, 1)).unwrap();
+
+        assert_eq!(file_version(&fs, dir, 1), Some(LEGACY_FORMAT_VERSION));
+
+        let report = Upgrader::upgrade(fs.as_ref(), dir, 1024, 1024).unwrap();
+        assert_eq!(report.upgraded, vec![1, 2]);
+        assert_eq!(report.foreign, vec![3]);
+        assert_eq!(report.entries, 4);
+        assert_eq!(file_version(&fs, dir, 1), Some(CURRENT_FORMAT_VERSION));
+        assert_eq!(file_version(&fs, dir, 2), Some(CURRENT_FORMAT_VERSION));
+        assert!(!fs.exists(&upgrade_tmp_path(dir, 1)));
+
+        // 重复执行是幂等的
+        let report = Upgrader::upgrade(fs.as_ref(), dir, 1024, 1024).unwrap();
+        assert!(report.upgraded.is_empty());
+        assert_eq!(report.up_to_date, vec![1, 2]);
+
+        // 升级后的目录可以正常打开
+        let watcher = config::ConfigWatcher::new("non_existent.conf").unwrap();
+        let mut cfg = watcher.get();
+        cfg.data_dir = path.to_string();
+        watcher.override_config(cfg);
+        let mut kv = KVStore::new(watcher, fs).unwrap();
+        kv.restore().unwrap();
+        assert!(kv.get("k1").unwrap().is_none());
+        assert_eq!(kv.get("k2").unwrap().unwrap().value, b"v2");
+        let k3 = kv.get("k3").unwrap().unwrap();
+        assert_eq!(k3.value, b"v3");
+        assert_eq!(k3.expire_at(), Some(u64::MAX));
+        assert_eq!(k3.sequence_number, 4);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod base64;
+pub mod glob;
+pub mod hex;
+pub mod json;
 pub mod varint;
+pub use base64::*;
+pub use glob::*;
+pub use hex::*;
+pub use json::*;
 pub use varint::*;
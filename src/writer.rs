@@ -1,5 +1,5 @@
 use crate::error::TitaniumError;
-use crate::log_entry::LogEntry;
+use crate::log_entry::{FILE_HEADER_SIZE, FileHeader, LogEntry};
 use crate::storage::Storage;
 use std::io;
 use std::io::Write;
@@ -10,20 +10,26 @@ pub struct Writer<W: Storage> {
 }
 
 impl<W: Storage> Writer<W> {
+    /// 用于追加写已存在的文件，调用者需保证 inner 的游标已位于 offset 处
     pub fn new(inner: W, offset: u64) -> Self {
         Self {
-            // TODO: [File Header] Write a fixed-length header at the beginning of new files (offset == 0).
-            // Layout: MagicNumber(4B) + Version(1B) + EncryptionSalt(Optional).
-            // Example: b"TITN" + 0x01
-            // This helps in identifying valid data files and handling format migrations.
             writer: io::BufWriter::new(inner),
             current_offset: offset,
-            // 💡 思考：如果是追加模式，这里应该 seek 到文件末尾获取初始 offset
-            // TODO: Ensure the inner writer is actually at the correct offset if appending to an existing file.
-            // 但目前 Day 2 假设新文件，0 是可以的。
         }
     }
 
+    /// 用于新创建的空文件：先写入文件头 (Magic + Version)，条目从文件头之后开始
+    pub fn create(inner: W) -> Result<Self, TitaniumError> {
+        let mut writer = io::BufWriter::new(inner);
+        FileHeader::current().encode_to(&mut writer)?;
+        // 推给内核，保证读路径 (read_at / len) 能立即看到文件头
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            current_offset: FILE_HEADER_SIZE,
+        })
+    }
+
     pub fn write_entry(&mut self, entry: &LogEntry) -> Result<u64, TitaniumError> {
         let offset = self.current_offset;
         let bytes_written = entry.encode_to(&mut self.writer)?;
@@ -56,8 +62,14 @@ impl<W: Storage> Writer<W> {
         Ok(())
     }
 
-    // 供 KVStore::restore 使用：当发现 active file 数据损坏并截断后，需要修正内存中的 offset
-    pub(crate) fn set_offset(&mut self, offset: u64) {
+    // 供 KVStore::restore 使用：当发现 active file 数据损坏时截断文件，
+    // 并将内存中的 offset 和文件游标一起移回截断点，保证后续追加写紧接在截断点之后
+    pub(crate) fn truncate(&mut self, offset: u64) -> Result<(), TitaniumError> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.set_len(offset)?;
+        file.seek(io::SeekFrom::Start(offset))?;
         self.current_offset = offset;
+        Ok(())
     }
 }